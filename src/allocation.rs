@@ -0,0 +1,178 @@
+// Frequency allocation database, used to put a name on detected signals
+//
+// The database is a CSV file with one allocation per line:
+//
+//     start MHz, stop MHz, service, typical bandwidth kHz
+//
+// Empty lines and lines starting with '#' are ignored, the bandwidth is optional.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub start_hz: u64,
+    pub stop_hz: u64,
+    pub service: String,
+    pub bandwidth_hz: Option<u64>,
+}
+
+impl Allocation {
+    /// True if the frequency falls in the allocation; single-frequency
+    /// allocations are widened by their typical bandwidth
+    pub fn contains(&self, hz: u64) -> bool {
+        let pad = if self.start_hz == self.stop_hz {
+            self.bandwidth_hz.unwrap_or(0) / 2
+        } else {
+            0
+        };
+        self.start_hz.saturating_sub(pad) <= hz && hz <= self.stop_hz + pad
+    }
+
+    pub fn span_hz(&self) -> u64 {
+        self.stop_hz - self.start_hz
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AllocationDb {
+    allocations: Vec<Allocation>,
+}
+
+impl AllocationDb {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("unable to read allocations {}", path.display()))?;
+        Self::parse(&contents).wrap_err_with(|| format!("invalid allocations {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut allocations = Vec::new();
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split(',').map(|x| x.trim()).collect();
+            if parts.len() < 3 {
+                return Err(eyre!(
+                    "line {}: expected start MHz, stop MHz, service",
+                    n + 1
+                ));
+            }
+
+            let start = parts[0]
+                .parse::<f64>()
+                .wrap_err_with(|| format!("line {}: bad start frequency", n + 1))?;
+            let stop = parts[1]
+                .parse::<f64>()
+                .wrap_err_with(|| format!("line {}: bad stop frequency", n + 1))?;
+            if stop < start {
+                return Err(eyre!("line {}: stop must not be below start", n + 1));
+            }
+
+            let bandwidth_hz = match parts.get(3) {
+                Some(bw) if !bw.is_empty() => Some(
+                    (bw.parse::<f64>()
+                        .wrap_err_with(|| format!("line {}: bad bandwidth", n + 1))?
+                        * 1000.0) as u64,
+                ),
+                _ => None,
+            };
+
+            allocations.push(Allocation {
+                start_hz: (start * 1_000_000.0).round() as u64,
+                stop_hz: (stop * 1_000_000.0).round() as u64,
+                service: parts[2].to_string(),
+                bandwidth_hz,
+            });
+        }
+
+        allocations.sort_by_key(|a| a.start_hz);
+
+        Ok(Self { allocations })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.allocations.len()
+    }
+
+    /// All allocations containing the given frequency
+    pub fn lookup_all(&self, hz: u64) -> impl Iterator<Item = &Allocation> {
+        self.allocations.iter().filter(move |a| a.contains(hz))
+    }
+
+    /// The most specific (narrowest) allocation containing the given frequency
+    pub fn lookup(&self, hz: u64) -> Option<&Allocation> {
+        self.lookup_all(hz).min_by_key(|a| a.span_hz())
+    }
+
+    /// Human readable annotation for a frequency, empty if no allocation matches
+    pub fn annotate(&self, hz: u64) -> String {
+        self.lookup(hz)
+            .map(|a| format!(" ({})", a.service))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB: &str = "\
+# start, stop, service, bandwidth
+2400, 2483.5, ISM 2.4 GHz
+2402, 2480, Bluetooth, 1000
+
+433.92, 433.92, LPD433, 25
+";
+
+    #[test]
+    fn allocations_parse_sorted_by_start() {
+        let db = AllocationDb::parse(DB).unwrap();
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.allocations[0].service, "LPD433");
+        assert_eq!(db.allocations[0].bandwidth_hz, Some(25_000));
+        assert_eq!(db.allocations[1].stop_hz, 2_483_500_000);
+        assert_eq!(db.allocations[1].bandwidth_hz, None);
+        assert!(AllocationDb::parse("# nothing\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn lookup_prefers_the_narrowest_allocation() {
+        let db = AllocationDb::parse(DB).unwrap();
+        assert_eq!(db.annotate(2_440_000_000), " (Bluetooth)");
+        assert_eq!(db.annotate(2_401_000_000), " (ISM 2.4 GHz)");
+        assert_eq!(db.lookup_all(2_440_000_000).count(), 2);
+        assert_eq!(db.annotate(100_000_000), "");
+    }
+
+    #[test]
+    fn single_frequencies_are_widened_by_their_bandwidth() {
+        let db = AllocationDb::parse(DB).unwrap();
+        assert_eq!(db.annotate(433_930_000), " (LPD433)");
+        assert_eq!(db.annotate(433_932_500), " (LPD433)");
+        assert_eq!(db.annotate(433_933_000), "");
+    }
+
+    #[test]
+    fn malformed_lines_are_refused_with_their_number() {
+        let error = |contents: &str| AllocationDb::parse(contents).unwrap_err().to_string();
+        assert_eq!(
+            error("1, 2, A\n3, 4\n"),
+            "line 2: expected start MHz, stop MHz, service"
+        );
+        assert_eq!(error("x, 2, A"), "line 1: bad start frequency");
+        assert_eq!(error("1, y, A"), "line 1: bad stop frequency");
+        assert_eq!(error("2, 1, A"), "line 1: stop must not be below start");
+        assert_eq!(error("1, 2, A, wide"), "line 1: bad bandwidth");
+    }
+}
@@ -0,0 +1,361 @@
+// Axis, time and colour bar annotations drawn in the margins of waterfall images
+
+use std::convert::Infallible;
+use std::ops::Deref;
+
+use chrono::{DateTime, Utc};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use crate::ticks::{decimals_for_step, nice_step, nice_ticks, nice_time_step};
+
+/// Size in pixels of a character of the annotation font
+pub const CHAR_WIDTH: u32 = 6;
+pub const CHAR_HEIGHT: u32 = 10;
+
+/// Margins added around the waterfall when annotating
+pub const MARGIN_LEFT: u32 = 60;
+pub const MARGIN_TOP: u32 = 32;
+pub const MARGIN_RIGHT: u32 = 64;
+
+const BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+const FOREGROUND: Rgb<u8> = Rgb([220, 220, 220]);
+const TICK_LENGTH: u32 = 4;
+
+// Minimum distance in pixels between two labelled ticks
+const MIN_FREQ_TICK_SPACING: u32 = 70;
+const MIN_TIME_TICK_SPACING: u32 = 20;
+const MIN_DB_TICK_SPACING: u32 = 16;
+
+// Lets embedded-graphics draw text straight into an image
+struct ImageTarget<'a>(&'a mut RgbImage);
+
+impl OriginDimensions for ImageTarget<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+
+impl DrawTarget for ImageTarget<'_> {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let (width, height) = self.0.dimensions();
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 && (point.x as u32) < width && (point.y as u32) < height
+            {
+                self.0.put_pixel(
+                    point.x as u32,
+                    point.y as u32,
+                    Rgb([color.r(), color.g(), color.b()]),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * CHAR_WIDTH
+}
+
+/// Draw text with its top left corner at (x, y), clipped to the image
+pub fn draw_text(img: &mut RgbImage, x: i32, y: i32, text: &str, color: Rgb<u8>) {
+    let style = MonoTextStyle::new(&FONT_6X10, Rgb888::new(color[0], color[1], color[2]));
+    let _ = Text::with_baseline(text, Point::new(x, y), style, Baseline::Top)
+        .draw(&mut ImageTarget(img));
+}
+
+/// What the margins of a waterfall image describe
+pub struct WaterfallAnnotation<'a> {
+    pub title: &'a str,
+    pub hz_low: u64,
+    pub hz_high: u64,
+    pub min_db: f32,
+    pub max_db: f32,
+}
+
+/// Place a waterfall in an annotated frame
+///
+/// `data` holds an optional band header of `header_height` rows followed by
+/// one row per entry of `timestamps`, the time of each row from top to
+/// bottom. `color` maps a dB value to the colour used in the waterfall, for
+/// the colour bar.
+pub fn annotate<C: Deref<Target = [u8]>>(
+    data: &ImageBuffer<Rgb<u8>, C>,
+    header_height: u32,
+    annotation: &WaterfallAnnotation,
+    timestamps: &[DateTime<Utc>],
+    color: impl Fn(f32) -> Rgb<u8>,
+) -> RgbImage {
+    let row_len = data.width() as usize * 3;
+    let (header, rows) = data.as_raw().split_at(header_height as usize * row_len);
+    let header = RgbImage::from_raw(data.width(), header_height, header.to_vec())
+        .expect("header rows do not match the image width");
+    let (mut frame, top) = Frame::new(&header, annotation, data.height(), color);
+
+    let mut img = RgbImage::new(top.width(), top.height() + timestamps.len() as u32);
+    image::imageops::replace(&mut img, &top, 0, 0);
+
+    let rows = rows.chunks_exact(row_len);
+    for (y, (row, t)) in (top.height()..).zip(rows.zip(timestamps)) {
+        let framed = frame.row(row, *t);
+        let start = y as usize * framed.len();
+        img.as_mut()[start..start + framed.len()].copy_from_slice(&framed);
+    }
+
+    img
+}
+
+/// Annotated frame around a waterfall written a row at a time
+///
+/// The title, the frequency axis and the band header form the top rows,
+/// returned by `new`. Each data row then gets its margins as it is added:
+/// time ticks and labels on the left, the colour bar on the right. Labels
+/// hang below their tick, the rows above it may already be written.
+pub struct Frame {
+    /// Left margin of the next rows, which a label may run into
+    left: RgbImage,
+    /// Right margin of the rows the colour bar and its labels span
+    right: RgbImage,
+    /// Number of data rows framed so far
+    rows: u32,
+    first: Option<DateTime<Utc>>,
+    previous: Option<DateTime<Utc>>,
+    /// First row a time label may start on, below the previous label
+    label_row: i64,
+}
+
+impl Frame {
+    /// Lay out the frame of a waterfall below the band header `header`
+    ///
+    /// The colour bar spans `bar_height` rows from the top of the band header,
+    /// `color` maps a dB value to its colour. Returns the frame and its top rows.
+    pub fn new(
+        header: &RgbImage,
+        annotation: &WaterfallAnnotation,
+        bar_height: u32,
+        color: impl Fn(f32) -> Rgb<u8>,
+    ) -> (Self, RgbImage) {
+        let data_width = header.width();
+        let width = MARGIN_LEFT + data_width + MARGIN_RIGHT;
+        let top_height = MARGIN_TOP + header.height();
+
+        // the rows below the top hold what spills over from it: the colour
+        // bar and its last label, and the unit of the time axis
+        let height = (top_height + CHAR_HEIGHT).max(MARGIN_TOP + bar_height + CHAR_HEIGHT);
+        let mut img = RgbImage::from_pixel(width, height, BACKGROUND);
+
+        image::imageops::replace(&mut img, header, MARGIN_LEFT as i64, MARGIN_TOP as i64);
+        draw_text(&mut img, 2, 2, annotation.title, FOREGROUND);
+        draw_frequency_axis(&mut img, data_width, annotation);
+        draw_color_bar(&mut img, data_width, bar_height, annotation, color);
+
+        // above the first row, but below the MHz label when there is no band
+        // header to leave room; time labels it would cover are left out
+        let unit_y = (top_height - CHAR_HEIGHT - 1).max(MARGIN_TOP - TICK_LENGTH);
+        draw_text(&mut img, 2, unit_y as i32, "UTC", FOREGROUND);
+
+        let frame = Self {
+            left: image::imageops::crop_imm(&img, 0, top_height, MARGIN_LEFT, CHAR_HEIGHT)
+                .to_image(),
+            right: image::imageops::crop_imm(
+                &img,
+                MARGIN_LEFT + data_width,
+                top_height,
+                MARGIN_RIGHT,
+                height - top_height,
+            )
+            .to_image(),
+            rows: 0,
+            first: None,
+            previous: None,
+            label_row: (unit_y + CHAR_HEIGHT + 1) as i64 - top_height as i64,
+        };
+        let top = image::imageops::crop_imm(&img, 0, 0, width, top_height).to_image();
+        (frame, top)
+    }
+
+    /// Frame the next data row, of RGB pixels, taken at `time`
+    pub fn row(&mut self, data: &[u8], time: DateTime<Utc>) -> Vec<u8> {
+        let row = self.rows;
+        self.rows += 1;
+        let first = *self.first.get_or_insert(time);
+
+        if let Some(previous) = self.previous.replace(time) {
+            // pick a round interval that keeps labels at least a few rows
+            // apart, from the mean sweep period so far
+            let period = (time - first).num_milliseconds() as f64 / 1000.0 / row as f64;
+            let step = nice_time_step(period * MIN_TIME_TICK_SPACING as f64);
+
+            if previous.timestamp().div_euclid(step) != time.timestamp().div_euclid(step) {
+                for x in MARGIN_LEFT - TICK_LENGTH..MARGIN_LEFT {
+                    self.left.put_pixel(x, 0, FOREGROUND);
+                }
+                if row as i64 >= self.label_row {
+                    let format = if step < 60 { "%H:%M:%S" } else { "%H:%M" };
+                    let label = time.format(format).to_string();
+                    draw_text(&mut self.left, 2, 0, &label, FOREGROUND);
+                    self.label_row = (row + CHAR_HEIGHT + 1) as i64;
+                }
+            }
+        }
+
+        let margin = MARGIN_LEFT as usize * 3;
+        let mut framed = Vec::with_capacity(margin + data.len() + MARGIN_RIGHT as usize * 3);
+        framed.extend_from_slice(&self.left.as_raw()[..margin]);
+        framed.extend_from_slice(data);
+        if row < self.right.height() {
+            let start = (row * MARGIN_RIGHT * 3) as usize;
+            framed
+                .extend_from_slice(&self.right.as_raw()[start..start + MARGIN_RIGHT as usize * 3]);
+        } else {
+            framed.extend(std::iter::repeat_n(BACKGROUND.0, MARGIN_RIGHT as usize).flatten());
+        }
+
+        // move the rest of a label up to the next row
+        let left = self.left.as_mut();
+        left.copy_within(margin.., 0);
+        let last = left.len() - margin;
+        left[last..].fill(0);
+
+        framed
+    }
+}
+
+fn draw_frequency_axis(img: &mut RgbImage, data_width: u32, annotation: &WaterfallAnnotation) {
+    let lo = annotation.hz_low as f64 / 1_000_000.0;
+    let hi = annotation.hz_high as f64 / 1_000_000.0;
+    if hi <= lo {
+        return;
+    }
+
+    let max_ticks = (data_width / MIN_FREQ_TICK_SPACING).max(1) as usize;
+    let decimals = decimals_for_step(nice_step(hi - lo, max_ticks));
+    let label_y = MARGIN_TOP - TICK_LENGTH - CHAR_HEIGHT - 2;
+
+    draw_text(img, 2, label_y as i32, "MHz", FOREGROUND);
+
+    for mhz in nice_ticks(lo, hi, max_ticks) {
+        let x = MARGIN_LEFT + (((mhz - lo) / (hi - lo)) * (data_width - 1) as f64) as u32;
+        for y in MARGIN_TOP - TICK_LENGTH..MARGIN_TOP {
+            img.put_pixel(x, y, FOREGROUND);
+        }
+        let label = format!("{:.*}", decimals, mhz);
+        let label_x = x as i32 - text_width(&label) as i32 / 2;
+        draw_text(img, label_x, label_y as i32, &label, FOREGROUND);
+    }
+}
+
+fn draw_color_bar(
+    img: &mut RgbImage,
+    data_width: u32,
+    bar_height: u32,
+    annotation: &WaterfallAnnotation,
+    color: impl Fn(f32) -> Rgb<u8>,
+) {
+    let x0 = MARGIN_LEFT + data_width + 6;
+    let bar_width = 10;
+    let top = MARGIN_TOP;
+    let bottom = (MARGIN_TOP + bar_height).saturating_sub(1);
+    if bottom <= top {
+        return;
+    }
+    let span = (bottom - top) as f32;
+    let (min_db, max_db) = (annotation.min_db, annotation.max_db);
+    if max_db <= min_db {
+        return;
+    }
+
+    // next to the bar, leaving room for the last frequency label
+    draw_text(
+        img,
+        (x0 + bar_width + 5) as i32,
+        (MARGIN_TOP - CHAR_HEIGHT - 2) as i32,
+        "dB",
+        FOREGROUND,
+    );
+
+    for y in top..=bottom {
+        let db = max_db - (y - top) as f32 / span * (max_db - min_db);
+        let c = color(db);
+        for x in x0..x0 + bar_width {
+            img.put_pixel(x, y, c);
+        }
+    }
+
+    let max_ticks = ((bottom - top) / MIN_DB_TICK_SPACING).max(1) as usize;
+    let decimals = decimals_for_step(nice_step((max_db - min_db) as f64, max_ticks));
+    for db in nice_ticks(min_db as f64, max_db as f64, max_ticks) {
+        let y = top + ((max_db as f64 - db) / (max_db - min_db) as f64 * span as f64) as u32;
+        for x in x0 + bar_width..x0 + bar_width + 3 {
+            img.put_pixel(x, y, FOREGROUND);
+        }
+        draw_text(
+            img,
+            (x0 + bar_width + 5) as i32,
+            y as i32 - CHAR_HEIGHT as i32 / 2,
+            &format!("{:.*}", decimals, db),
+            FOREGROUND,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(min_db: f32, max_db: f32) -> RgbImage {
+        let data = RgbImage::from_pixel(200, 100, Rgb([1, 2, 3]));
+        let timestamps: Vec<DateTime<Utc>> = (0..100)
+            .map(|s| DateTime::from_timestamp(s, 0).unwrap())
+            .collect();
+        let annotation = WaterfallAnnotation {
+            title: "test",
+            hz_low: 100_000_000,
+            hz_high: 200_000_000,
+            min_db,
+            max_db,
+        };
+        annotate(&data, 0, &annotation, &timestamps, |_| Rgb([255, 0, 0]))
+    }
+
+    #[test]
+    fn the_data_is_placed_inside_the_margins() {
+        let img = frame(-100.0, -10.0);
+        assert_eq!(
+            img.dimensions(),
+            (MARGIN_LEFT + 200 + MARGIN_RIGHT, MARGIN_TOP + 100)
+        );
+        assert_eq!(img.get_pixel(MARGIN_LEFT, MARGIN_TOP), &Rgb([1, 2, 3]));
+        assert_eq!(
+            img.get_pixel(MARGIN_LEFT + 199, MARGIN_TOP + 99),
+            &Rgb([1, 2, 3])
+        );
+        // the colour bar
+        assert_eq!(
+            img.get_pixel(MARGIN_LEFT + 200 + 6, MARGIN_TOP + 50),
+            &Rgb([255, 0, 0])
+        );
+    }
+
+    #[test]
+    fn an_empty_db_range_leaves_out_the_colour_bar() {
+        for (min_db, max_db) in [(-50.0, -50.0), (-10.0, -100.0)] {
+            let img = frame(min_db, max_db);
+            assert_eq!(
+                img.get_pixel(MARGIN_LEFT + 200 + 6, MARGIN_TOP + 50),
+                &BACKGROUND
+            );
+        }
+    }
+}
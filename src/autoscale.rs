@@ -0,0 +1,189 @@
+// Automatic dB range selection from the noise floor and the peaks of recent sweeps
+
+use std::collections::VecDeque;
+
+use crate::peaks::percentile;
+use crate::sweep::Sweep;
+
+/// Fraction of the bins below the noise floor estimate
+const NOISE_PERCENTILE: f32 = 0.2;
+/// Fraction of the bins below the peak estimate
+const PEAK_PERCENTILE: f32 = 0.999;
+/// Headroom added below the noise floor and above the peaks
+const MARGIN_DB: f32 = 3.0;
+/// Narrowest range ever selected
+const MIN_SPAN_DB: f32 = 20.0;
+
+pub struct AutoScale {
+    window: usize,
+    /// Per-sweep (noise floor, peak) estimates of the last `window` sweeps
+    recent: VecDeque<(f32, f32)>,
+    /// Fraction of the way the range moves toward the new estimate per sweep,
+    /// `None` to lock the range once the window is full
+    tracking: Option<f32>,
+    range: Option<(f32, f32)>,
+}
+
+impl AutoScale {
+    pub fn new(window: usize, tracking: Option<f32>) -> Self {
+        Self {
+            window: window.max(1),
+            recent: VecDeque::new(),
+            tracking,
+            range: None,
+        }
+    }
+
+    /// Current (min_db, max_db), `None` until the first sweep
+    pub fn range(&self) -> Option<(f32, f32)> {
+        self.range
+    }
+
+    /// Whether the range is estimated from a full window of sweeps
+    pub fn settled(&self) -> bool {
+        self.recent.len() >= self.window
+    }
+
+    /// Forget everything and start estimating again
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.range = None;
+    }
+
+    pub fn update(&mut self, sweep: &Sweep) -> Option<(f32, f32)> {
+        if sweep.db.is_empty() {
+            return self.range;
+        }
+
+        let locked = self.tracking.is_none() && self.recent.len() >= self.window;
+        if locked {
+            return self.range;
+        }
+
+        self.recent.push_back((
+            percentile(&sweep.db, NOISE_PERCENTILE),
+            percentile(&sweep.db, PEAK_PERCENTILE),
+        ));
+        if self.recent.len() > self.window {
+            self.recent.pop_front();
+        }
+
+        let n = self.recent.len() as f32;
+        let noise = self.recent.iter().map(|r| r.0).sum::<f32>() / n;
+        let peak = self
+            .recent
+            .iter()
+            .map(|r| r.1)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let target = target_range(noise, peak);
+
+        self.range = Some(match (self.range, self.tracking) {
+            // still filling the window, or no previous range to smooth
+            (None, _) | (Some(_), None) => target,
+            (Some((lo, hi)), Some(rate)) => {
+                (lo + (target.0 - lo) * rate, hi + (target.1 - hi) * rate)
+            }
+        });
+
+        self.range
+    }
+}
+
+fn target_range(noise: f32, peak: f32) -> (f32, f32) {
+    let lo = noise - MARGIN_DB;
+    let hi = (peak + MARGIN_DB).max(lo + MIN_SPAN_DB);
+    (lo, hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    // 1000 bins at the noise floor but for two at the peak, so that both
+    // percentiles land exactly on them
+    fn sweep(noise: f32, peak: f32) -> Sweep {
+        let mut db = vec![noise; 1000];
+        db[500] = peak;
+        db[501] = peak;
+        Sweep {
+            hz_low: 100_000_000,
+            hz_high: 110_000_000,
+            hz_bin_width: 10_000.0,
+            db,
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn the_range_brackets_the_noise_floor_and_the_peaks() {
+        let mut autoscale = AutoScale::new(3, None);
+        assert_eq!(autoscale.range(), None);
+        assert_eq!(autoscale.update(&sweep(-90.0, -40.0)), Some((-93.0, -37.0)));
+        assert_eq!(autoscale.range(), Some((-93.0, -37.0)));
+    }
+
+    #[test]
+    fn the_window_averages_the_noise_and_keeps_the_highest_peak() {
+        let mut autoscale = AutoScale::new(3, None);
+        autoscale.update(&sweep(-90.0, -30.0));
+        assert_eq!(autoscale.update(&sweep(-80.0, -40.0)), Some((-88.0, -27.0)));
+    }
+
+    #[test]
+    fn the_range_is_never_narrower_than_the_minimum_span() {
+        let mut autoscale = AutoScale::new(1, None);
+        assert_eq!(autoscale.update(&sweep(-90.0, -85.0)), Some((-93.0, -73.0)));
+        // a flat sweep has no peaks at all
+        let mut autoscale = AutoScale::new(1, None);
+        assert_eq!(autoscale.update(&sweep(-60.0, -60.0)), Some((-63.0, -43.0)));
+    }
+
+    #[test]
+    fn without_tracking_the_range_locks_once_settled() {
+        let mut autoscale = AutoScale::new(3, None);
+        for _ in 0..2 {
+            autoscale.update(&sweep(-90.0, -40.0));
+            assert!(!autoscale.settled());
+        }
+        autoscale.update(&sweep(-90.0, -40.0));
+        assert!(autoscale.settled());
+
+        assert_eq!(autoscale.update(&sweep(-50.0, -10.0)), Some((-93.0, -37.0)));
+        assert!(autoscale.settled());
+    }
+
+    #[test]
+    fn tracking_moves_part_of_the_way_to_each_new_estimate() {
+        let mut autoscale = AutoScale::new(1, Some(0.5));
+        autoscale.update(&sweep(-90.0, -40.0));
+        assert!(autoscale.settled());
+        // target (-73, -17)
+        assert_eq!(autoscale.update(&sweep(-70.0, -20.0)), Some((-83.0, -27.0)));
+        assert_eq!(autoscale.update(&sweep(-70.0, -20.0)), Some((-78.0, -22.0)));
+    }
+
+    #[test]
+    fn empty_sweeps_are_ignored() {
+        let mut autoscale = AutoScale::new(1, None);
+        let empty = Sweep {
+            db: Vec::new(),
+            ..sweep(0.0, 0.0)
+        };
+        assert_eq!(autoscale.update(&empty), None);
+        assert!(!autoscale.settled());
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut autoscale = AutoScale::new(0, None);
+        autoscale.update(&sweep(-90.0, -40.0));
+        assert!(autoscale.settled());
+
+        autoscale.reset();
+        assert_eq!(autoscale.range(), None);
+        assert!(!autoscale.settled());
+        assert_eq!(autoscale.update(&sweep(-50.0, -10.0)), Some((-53.0, -7.0)));
+    }
+}
@@ -0,0 +1,309 @@
+// Band plans: named frequency ranges drawn as a labelled strip over the spectrum
+//
+// A band plan file is a plain text file with one band per line:
+//
+//     name, start MHz, stop MHz, #rrggbb
+//
+// Empty lines and lines starting with '#' are ignored, the colour is optional.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+/// Colour used for bands that do not specify one
+pub const DEFAULT_BAND_COLOR: (u8, u8, u8) = (90, 90, 160);
+
+/// Names of the built-in band plans, usable in place of a file path
+pub const BUILTIN_BAND_PLANS: &[&str] = &["ism", "wifi24", "wifi5", "ble", "lte"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Band {
+    pub name: String,
+    pub start_hz: u64,
+    pub stop_hz: u64,
+    pub color: (u8, u8, u8),
+}
+
+impl Band {
+    fn new(name: impl Into<String>, start_mhz: f64, stop_mhz: f64, color: (u8, u8, u8)) -> Self {
+        Self {
+            name: name.into(),
+            start_hz: (start_mhz * 1_000_000.0).round() as u64,
+            stop_hz: (stop_mhz * 1_000_000.0).round() as u64,
+            color,
+        }
+    }
+
+    pub fn center_hz(&self) -> u64 {
+        (self.start_hz + self.stop_hz) / 2
+    }
+
+    /// True if the band overlaps the [hz_low, hz_high] range
+    pub fn overlaps(&self, hz_low: u64, hz_high: u64) -> bool {
+        self.start_hz < hz_high && self.stop_hz > hz_low
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandPlan {
+    pub name: String,
+    pub bands: Vec<Band>,
+}
+
+impl BandPlan {
+    /// Load a built-in band plan by name, or a band plan file by path
+    pub fn load(spec: &str) -> Result<Self> {
+        if let Some(plan) = Self::builtin(spec) {
+            return Ok(plan);
+        }
+        Self::from_file(spec)
+    }
+
+    /// Load all the given band plans, in order
+    pub fn load_all(specs: &[String]) -> Result<Vec<Self>> {
+        specs.iter().map(|spec| Self::load(spec)).collect()
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("unable to read band plan {}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::parse(&name, &contents)
+            .wrap_err_with(|| format!("invalid band plan {}", path.display()))
+    }
+
+    pub fn parse(name: &str, contents: &str) -> Result<Self> {
+        let mut bands = Vec::new();
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split(',').map(|x| x.trim()).collect();
+            if parts.len() < 3 {
+                return Err(eyre!("line {}: expected name, start MHz, stop MHz", n + 1));
+            }
+
+            let start = parts[1]
+                .parse::<f64>()
+                .wrap_err_with(|| format!("line {}: bad start frequency", n + 1))?;
+            let stop = parts[2]
+                .parse::<f64>()
+                .wrap_err_with(|| format!("line {}: bad stop frequency", n + 1))?;
+            if stop <= start {
+                return Err(eyre!("line {}: stop must be above start", n + 1));
+            }
+
+            let color = match parts.get(3) {
+                Some(c) if !c.is_empty() => {
+                    parse_hex_color(c).ok_or_else(|| eyre!("line {}: bad colour {:?}", n + 1, c))?
+                }
+                _ => DEFAULT_BAND_COLOR,
+            };
+
+            bands.push(Band::new(parts[0], start, stop, color));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            bands,
+        })
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let bands = match name {
+            "ism" => ism_bands(),
+            "wifi24" => wifi24_bands(),
+            "wifi5" => wifi5_bands(),
+            "ble" => ble_bands(),
+            "lte" => lte_bands(),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            bands,
+        })
+    }
+
+    /// Bands overlapping the [hz_low, hz_high] range
+    pub fn bands_in(&self, hz_low: u64, hz_high: u64) -> impl Iterator<Item = &Band> {
+        self.bands
+            .iter()
+            .filter(move |b| b.overlaps(hz_low, hz_high))
+    }
+}
+
+/// Parse a "#rrggbb" colour
+pub fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
+    let s = s.strip_prefix('#')?;
+    if s.len() != 6 {
+        return None;
+    }
+    let r = u8::from_str_radix(&s[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&s[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&s[4..6], 16).ok()?;
+    Some((r, g, b))
+}
+
+fn ism_bands() -> Vec<Band> {
+    vec![
+        Band::new("ISM 433", 433.05, 434.79, (200, 120, 40)),
+        Band::new("SRD 868", 863.0, 870.0, (40, 160, 120)),
+        Band::new("ISM 915", 902.0, 928.0, (160, 60, 160)),
+        Band::new("ISM 2.4G", 2400.0, 2483.5, (60, 120, 200)),
+        Band::new("ISM 5.8G", 5725.0, 5875.0, (200, 80, 80)),
+    ]
+}
+
+// 20 MHz channels, alternating colours so adjacent channels can be told apart
+fn wifi24_bands() -> Vec<Band> {
+    (1..=14)
+        .map(|ch| {
+            let center = if ch == 14 {
+                2484.0
+            } else {
+                2407.0 + 5.0 * ch as f64
+            };
+            let color = if ch % 2 == 0 {
+                (40, 110, 180)
+            } else {
+                (30, 80, 140)
+            };
+            Band::new(format!("WiFi {}", ch), center - 10.0, center + 10.0, color)
+        })
+        .collect()
+}
+
+fn wifi5_bands() -> Vec<Band> {
+    let channels = (36..=64)
+        .step_by(4)
+        .chain((100..=144).step_by(4))
+        .chain((149..=177).step_by(4));
+    channels
+        .enumerate()
+        .map(|(i, ch)| {
+            let center = 5000.0 + 5.0 * ch as f64;
+            let color = if i % 2 == 0 {
+                (40, 150, 110)
+            } else {
+                (30, 110, 80)
+            };
+            Band::new(format!("WiFi {}", ch), center - 10.0, center + 10.0, color)
+        })
+        .collect()
+}
+
+fn ble_bands() -> Vec<Band> {
+    [(37, 2402.0), (38, 2426.0), (39, 2480.0)]
+        .iter()
+        .map(|&(ch, center)| {
+            Band::new(
+                format!("BLE {}", ch),
+                center - 1.0,
+                center + 1.0,
+                (220, 180, 40),
+            )
+        })
+        .collect()
+}
+
+// Common European/worldwide FDD LTE bands, uplink and downlink
+fn lte_bands() -> Vec<Band> {
+    let bands: &[(u32, f64, f64, f64, f64)] = &[
+        (1, 1920.0, 1980.0, 2110.0, 2170.0),
+        (3, 1710.0, 1785.0, 1805.0, 1880.0),
+        (7, 2500.0, 2570.0, 2620.0, 2690.0),
+        (8, 880.0, 915.0, 925.0, 960.0),
+        (20, 832.0, 862.0, 791.0, 821.0),
+        (28, 703.0, 748.0, 758.0, 803.0),
+    ];
+    bands
+        .iter()
+        .flat_map(|&(n, ul_lo, ul_hi, dl_lo, dl_hi)| {
+            [
+                Band::new(format!("LTE B{} UL", n), ul_lo, ul_hi, (150, 70, 70)),
+                Band::new(format!("LTE B{} DL", n), dl_lo, dl_hi, (190, 90, 60)),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands_parse_with_and_without_colour() {
+        let plan = BandPlan::parse(
+            "test",
+            "# comment\n\n  Ham 2m, 144, 146, #ff8000\nAir, 118.0, 136.975\nPMR, 446.0, 446.2,\n",
+        )
+        .unwrap();
+        assert_eq!(plan.name, "test");
+        assert_eq!(
+            plan.bands,
+            [
+                Band::new("Ham 2m", 144.0, 146.0, (255, 128, 0)),
+                Band::new("Air", 118.0, 136.975, DEFAULT_BAND_COLOR),
+                Band::new("PMR", 446.0, 446.2, DEFAULT_BAND_COLOR),
+            ]
+        );
+        assert_eq!(plan.bands[1].stop_hz, 136_975_000);
+    }
+
+    #[test]
+    fn malformed_lines_are_refused_with_their_number() {
+        let error = |contents: &str| BandPlan::parse("test", contents).unwrap_err().to_string();
+        assert_eq!(
+            error("A, 1, 2\nB, 3\n"),
+            "line 2: expected name, start MHz, stop MHz"
+        );
+        assert_eq!(error("A, one, 2"), "line 1: bad start frequency");
+        assert_eq!(error("A, 1, two"), "line 1: bad stop frequency");
+        assert_eq!(error("A, 2, 1"), "line 1: stop must be above start");
+        assert_eq!(error("A, 1, 1"), "line 1: stop must be above start");
+        assert_eq!(error("# c\nA, 1, 2, red"), "line 2: bad colour \"red\"");
+    }
+
+    #[test]
+    fn hex_colours_need_a_hash_and_six_digits() {
+        assert_eq!(parse_hex_color("#0a10FF"), Some((10, 16, 255)));
+        assert_eq!(parse_hex_color("0a10ff"), None);
+        assert_eq!(parse_hex_color("#0a10f"), None);
+        assert_eq!(parse_hex_color("#0a10fg"), None);
+    }
+
+    #[test]
+    fn builtins_load_by_name() {
+        for name in BUILTIN_BAND_PLANS {
+            let plan = BandPlan::load(name).unwrap();
+            assert!(!plan.bands.is_empty(), "{}", name);
+            assert!(
+                plan.bands.iter().all(|b| b.start_hz < b.stop_hz),
+                "{}",
+                name
+            );
+        }
+        assert!(BandPlan::load("no such plan or file").is_err());
+    }
+
+    #[test]
+    fn bands_in_keeps_the_overlapping_ones() {
+        let plan = BandPlan::parse("test", "A, 100, 110\nB, 110, 120\nC, 130, 140").unwrap();
+        let names = |low: u64, high: u64| -> Vec<String> {
+            plan.bands_in(low * 1_000_000, high * 1_000_000)
+                .map(|b| b.name.clone())
+                .collect()
+        };
+        assert_eq!(names(105, 115), ["A", "B"]);
+        // touching edges do not overlap
+        assert_eq!(names(120, 130), Vec::<String>::new());
+        assert_eq!(names(0, 1000), ["A", "B", "C"]);
+    }
+}
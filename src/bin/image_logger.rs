@@ -1,63 +1,269 @@
-use clap::Parser;
-use hackrf_spectrum_analyzer::{run_sweep, sweep::Sweep, SweepParams};
+use chrono::TimeDelta;
+use clap::{ArgAction, Parser};
+use hackrf_spectrum_analyzer::{
+    allocation::AllocationDb,
+    bandplan::BandPlan,
+    decimate::{CombineMode, TimeDecimator},
+    peaks::{find_peaks, Peak},
+    run_sweep,
+    sweep::Sweep,
+    waterfall::{OutputConfig, OutputFormat, RotationPolicy, WaterfallWriter},
+    SweepParams,
+};
 use log::info;
-use std::{process::Command, sync::mpsc::channel, thread};
+use std::{path::PathBuf, sync::mpsc::channel, thread, time::Duration};
 
-fn render_image(sweeps: &[Sweep], max_db: f32, min_db: f32) {
-    let width = sweeps[0].db.len();
-    let height = sweeps.len();
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    sweep: SweepParams,
 
-    let mut imgbuf = image::ImageBuffer::new(width as u32, height as u32);
+    /// Directory the images are written to
+    #[arg(short = 'o', long, default_value = "/tmp")]
+    output_dir: PathBuf,
 
-    let gradient = colorous::INFERNO;
+    /// Image file name without extension; {date}, {time} (UTC, of the first sweep), {band} and {index} are replaced
+    #[arg(long, default_value = "sweep_{date}_{time}_{band}")]
+    filename: String,
 
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let sweep = &sweeps[y as usize];
-        let db = sweep.db[x as usize];
-        let db = (db - min_db) / (max_db - min_db);
-        let db = gradient.eval_continuous(db.into());
-        *pixel = image::Rgb([db.r, db.g, db.b]);
+    /// Image format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Tiff)]
+    format: OutputFormat,
+
+    /// Start a new image file after this many sweeps
+    #[arg(long, default_value_t = 3600)]
+    rotate_rows: u32,
+
+    /// Start a new image file after this many seconds
+    #[arg(long)]
+    rotate_seconds: Option<u64>,
+
+    /// Update the index row of the file being written at most every this many seconds
+    #[arg(long, default_value_t = 10)]
+    refresh_seconds: u64,
+
+    /// Rotate on wall-clock multiples of --rotate-seconds (e.g. 3600 rotates on the hour)
+    #[arg(long)]
+    rotate_align: bool,
+
+    /// Index CSV listing every image, defaults to index.csv in the output directory
+    #[arg(long)]
+    index: Option<PathBuf>,
+
+    /// Do not list the images in an index CSV
+    #[arg(long = "no-index", action = ArgAction::SetFalse, conflicts_with = "index")]
+    write_index: bool,
+
+    /// Write only the waterfall pixels, without axes, colour bar and title
+    #[arg(long)]
+    bare: bool,
+
+    /// Do not write the raw dB sidecar (.sweeps) next to each image
+    #[arg(long = "no-raw", action = ArgAction::SetFalse)]
+    raw: bool,
+
+    /// Combine the sweeps of each interval of this many seconds into one image row
+    #[arg(long)]
+    decimate: Option<f64>,
+
+    /// How sweeps are combined into a decimated row: max, mean or a percentile such as p90
+    #[arg(long, default_value = "max")]
+    decimate_mode: CombineMode,
+
+    /// With --decimate, also write the full resolution images next to the decimated overview
+    #[arg(long, requires = "decimate")]
+    full_resolution: bool,
+}
+
+impl Args {
+    fn output_config(&self, template: String) -> OutputConfig {
+        OutputConfig {
+            index: self.write_index.then(|| {
+                self.index
+                    .clone()
+                    .unwrap_or_else(|| self.output_dir.join("index.csv"))
+            }),
+            directory: self.output_dir.clone(),
+            template,
+            format: self.format,
+            rotation: RotationPolicy {
+                max_rows: self.rotate_rows.max(1),
+                max_duration: self.rotate_seconds.map(Duration::from_secs),
+                align_to_clock: self.rotate_align,
+            },
+            refresh: Duration::from_secs(self.refresh_seconds),
+            annotate: !self.bare,
+            raw: self.raw,
+        }
     }
+}
 
-    imgbuf.save("/tmp/sweep.tiff").unwrap();
+/// Maximum number of peaks tracked per sweep
+const MAX_PEAKS: usize = 8;
+
+// What the receiver thread gets
+enum Message {
+    Sweep(Sweep),
+    /// Ctrl-C or a termination signal, close the files and exit
+    Stop,
 }
 
-fn red_blue_color_map(db: f32, max_db: f32, min_db: f32) -> (u8, u8, u8) {
-    let db = (db - min_db) / (max_db - min_db);
-    let db = db * 255.0;
-    let db = db as u8;
-    let r = 255 - db;
-    let b = db;
-    (r, 0, b)
+// Log the peaks that were not already present in the previous sweep
+fn log_new_peaks(
+    sweep: &Sweep,
+    previous: &[Peak],
+    threshold: f32,
+    allocations: &AllocationDb,
+) -> Vec<Peak> {
+    let separation = (sweep.hz_bin_width * 5.0) as u64;
+    let peaks = find_peaks(sweep, threshold, separation, MAX_PEAKS);
+
+    for peak in &peaks {
+        if previous
+            .iter()
+            .all(|p| p.hz.abs_diff(peak.hz) >= separation)
+        {
+            info!(
+                "New peak at {:.3} MHz, {:.1} dB{}",
+                peak.hz as f64 / 1_000_000.0,
+                peak.db,
+                allocations.annotate(peak.hz)
+            );
+        }
+    }
+
+    peaks
+}
+
+// Add a sweep to the full resolution images and to the decimated overview
+fn write(
+    sweep: Sweep,
+    writer: &mut Option<WaterfallWriter>,
+    overview: &mut Option<(WaterfallWriter, TimeDecimator)>,
+) {
+    if let Some(writer) = writer {
+        writer
+            .push(&sweep)
+            .expect("Unable to write waterfall image");
+    }
+    if let Some((writer, decimator)) = overview {
+        if let Some(row) = decimator.push(sweep) {
+            writer.push(&row).expect("Unable to write overview image");
+        }
+    }
 }
 
 fn main() {
     env_logger::init();
-    let params = SweepParams::parse();
+    let args = Args::parse();
+    let params = args.sweep.clone();
+
+    params.pretty_print();
+
+    let band_plans = BandPlan::load_all(&params.band_plans).expect("Unable to load band plans");
+    let colormap = params.load_colormap().expect("Unable to load colour map");
+    let allocations = match &params.allocations {
+        Some(path) => AllocationDb::from_file(path).expect("Unable to load allocations"),
+        None => AllocationDb::default(),
+    };
 
-    params.PrettyPrint();
     let sw = run_sweep(&params);
 
     println!("{}", sw.pretty_print());
 
     let (tx, rx) = channel();
 
+    let stop = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(Message::Stop);
+    })
+    .expect("Unable to set the Ctrl-C handler");
+
     let params_clone = params.clone();
-    let sender = thread::spawn(move || loop {
+    thread::spawn(move || loop {
         let sw = run_sweep(&params_clone);
-        tx.send(sw).expect("Unable to send on channel");
+        // the receiver is gone once stopped
+        if tx.send(Message::Sweep(sw)).is_err() {
+            break;
+        }
     });
 
     let receiver = thread::spawn(move || {
-        let mut sweeps = Vec::new();
-        loop {
-            let sw = rx.recv().expect("Unable to receive on channel");
+        // Full resolution images, unless only the decimated overview is wanted
+        let mut writer = (args.decimate.is_none() || args.full_resolution).then(|| {
+            WaterfallWriter::new(
+                args.output_config(args.filename.clone()),
+                band_plans.clone(),
+                colormap.clone(),
+                &params,
+            )
+        });
+
+        // Decimated overview, each row combining the sweeps of an interval
+        let mut overview = args.decimate.map(|seconds| {
+            let config = args.output_config(format!("{}_{}s", args.filename, seconds));
+            let title = format!(
+                "{}, {} s {} per row",
+                params.summary(),
+                seconds,
+                args.decimate_mode
+            );
+            let decimator = TimeDecimator::new(
+                TimeDelta::milliseconds((seconds * 1000.0) as i64),
+                args.decimate_mode,
+            );
+            (
+                WaterfallWriter::new(config, band_plans.clone(), colormap.clone(), &params)
+                    .with_title(title),
+                decimator,
+            )
+        });
+
+        let mut autoscale = params.autoscale();
+        let mut settling = Vec::new();
+        let mut peaks = Vec::new();
+        while let Ok(Message::Sweep(sw)) = rx.recv() {
+            peaks = log_new_peaks(&sw, &peaks, params.peak_threshold, &allocations);
+
+            // the new range applies from the next file on
+            if let Some((min_db, max_db)) = autoscale.as_mut().and_then(|a| a.update(&sw)) {
+                if let Some(writer) = &mut writer {
+                    writer.set_db_range(min_db, max_db);
+                }
+                if let Some((writer, _)) = &mut overview {
+                    writer.set_db_range(min_db, max_db);
+                }
+            }
+
+            // hold the first sweeps back until the automatic range is picked,
+            // so that the first file is coloured with it too
+            settling.push(sw);
+            if autoscale.as_ref().is_some_and(|a| !a.settled()) {
+                continue;
+            }
 
-            sweeps.push(sw);
-            render_image(&sweeps, params.max_db, params.min_db);
+            for sw in settling.drain(..) {
+                write(sw, &mut writer, &mut overview);
+            }
+        }
+
+        // write what is still held back, then close the files and their index rows
+        info!("Stopping");
+        for sw in settling.drain(..) {
+            write(sw, &mut writer, &mut overview);
+        }
+        if let Some(writer) = &mut writer {
+            writer.finish().expect("Unable to write waterfall image");
+        }
+        if let Some((writer, decimator)) = &mut overview {
+            if let Some(row) = decimator.flush() {
+                writer.push(&row).expect("Unable to write overview image");
+            }
+            writer.finish().expect("Unable to write overview image");
         }
     });
 
-    sender.join().expect("The sender thread has panicked");
+    // the sender thread may be waiting on hackrf_sweep, it ends with the process
     receiver.join().expect("The receiver thread has panicked");
 }
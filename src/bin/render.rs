@@ -0,0 +1,251 @@
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use clap::Parser;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use hackrf_spectrum_analyzer::{
+    annotate::{annotate, WaterfallAnnotation},
+    colormap::ColorMap,
+    decimate::TimeDecimator,
+    raw::{RawReader, RAW_EXTENSION},
+    sweep::{DownscaleMode, Sweep},
+};
+use image::{Rgb, RgbImage};
+use log::info;
+
+/// Render a waterfall image from recorded sweeps, without the hardware
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Recordings to render, in time order: hackrf_sweep CSV output or .sweeps sidecars
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Output image, the format follows the extension (png, tiff, ...)
+    #[arg(short = 'o', long, default_value = "render.png")]
+    output: PathBuf,
+
+    /// Lowest frequency to keep, in MHz
+    #[arg(long)]
+    min_freq: Option<f64>,
+
+    /// Highest frequency to keep, in MHz
+    #[arg(long)]
+    max_freq: Option<f64>,
+
+    /// Drop sweeps before this UTC time (RFC 3339 or "YYYY-MM-DD HH:MM:SS")
+    #[arg(long, value_parser = parse_time)]
+    start: Option<DateTime<Utc>>,
+
+    /// Drop sweeps after this UTC time (RFC 3339 or "YYYY-MM-DD HH:MM:SS")
+    #[arg(long, value_parser = parse_time)]
+    end: Option<DateTime<Utc>>,
+
+    /// Combine the sweeps of each interval of this many seconds into one row
+    #[arg(long)]
+    bucket: Option<f64>,
+
+    /// How sweeps are combined into a row, and bins into a pixel when narrowing
+    #[arg(long, value_enum, default_value_t = DownscaleMode::Peak)]
+    aggregate: DownscaleMode,
+
+    /// Output width in pixels, defaults to one pixel per bin
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Colour map: a colorous gradient name (inferno, viridis, turbo, ...) or a gradient file
+    #[arg(long, default_value = "inferno")]
+    colormap: String,
+
+    /// Colour map gamma, below 1 brightens weak signals
+    #[arg(long, default_value_t = 1.0)]
+    gamma: f32,
+
+    /// Colour map contrast around the middle of the dB range
+    #[arg(long, default_value_t = 1.0)]
+    contrast: f32,
+
+    /// Minimum dB value of the colour range
+    #[arg(long, default_value_t = -100.0)]
+    min_db: f32,
+
+    /// Maximum dB value of the colour range
+    #[arg(long, default_value_t = -10.0)]
+    max_db: f32,
+
+    /// Image title, defaults to the time span and frequency range
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Write only the waterfall pixels, without axes, colour bar and title
+    #[arg(long)]
+    bare: bool,
+}
+
+fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+        .map(|t| t.and_utc())
+        .ok_or_else(|| format!("invalid time {:?}", s))
+}
+
+fn read_sweeps(path: &PathBuf) -> Result<Box<dyn Iterator<Item = Result<Sweep>>>> {
+    if path.extension().is_some_and(|e| e == RAW_EXTENSION) {
+        Ok(Box::new(RawReader::open(path)?))
+    } else {
+        let file =
+            File::open(path).wrap_err_with(|| format!("unable to open {}", path.display()))?;
+        Ok(Box::new(
+            Sweep::read_hackrf_sweep_recording(BufReader::new(file)).map(Ok),
+        ))
+    }
+}
+
+// Rows of the output image, built one (possibly aggregated) sweep at a time
+struct Rows {
+    target_width: Option<usize>,
+    mode: DownscaleMode,
+    colormap: ColorMap,
+    min_db: f32,
+    max_db: f32,
+    width: Option<usize>,
+    pixels: Vec<u8>,
+    timestamps: Vec<DateTime<Utc>>,
+    hz_low: u64,
+    hz_high: u64,
+}
+
+impl Rows {
+    fn push(&mut self, sweep: &Sweep) {
+        if sweep.db.is_empty() {
+            return;
+        }
+        let width = *self
+            .width
+            .get_or_insert_with(|| self.target_width.unwrap_or(sweep.db.len()));
+        if self.timestamps.is_empty() {
+            self.hz_low = sweep.hz_low;
+            self.hz_high = sweep.hz_high;
+        }
+
+        for db in sweep.resample(width, self.mode) {
+            let (r, g, b) = self.colormap.eval_db(db, self.min_db, self.max_db);
+            self.pixels.extend_from_slice(&[r, g, b]);
+        }
+        self.timestamps.push(sweep.timestamp);
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    color_eyre::install()?;
+
+    let args = Args::parse();
+    let crop = match (args.min_freq, args.max_freq) {
+        (None, None) => None,
+        (lo, hi) => Some((
+            (lo.unwrap_or(0.0) * 1_000_000.0) as u64,
+            hi.map(|hi| (hi * 1_000_000.0) as u64).unwrap_or(u64::MAX),
+        )),
+    };
+    let mut decimator = args.bucket.map(|b| {
+        TimeDecimator::new(
+            TimeDelta::milliseconds((b * 1000.0) as i64),
+            args.aggregate.into(),
+        )
+    });
+
+    let mut rows = Rows {
+        target_width: args.width,
+        mode: args.aggregate,
+        colormap: ColorMap::load(&args.colormap)?.with_adjustments(args.contrast, args.gamma),
+        min_db: args.min_db,
+        max_db: args.max_db,
+        width: None,
+        pixels: Vec::new(),
+        timestamps: Vec::new(),
+        hz_low: 0,
+        hz_high: 0,
+    };
+    let mut read = 0usize;
+
+    for path in &args.inputs {
+        info!("Reading {}", path.display());
+        for sweep in read_sweeps(path)? {
+            let sweep = sweep?;
+            read += 1;
+
+            if args.start.is_some_and(|t| sweep.timestamp < t)
+                || args.end.is_some_and(|t| sweep.timestamp > t)
+            {
+                continue;
+            }
+            let sweep = match crop {
+                Some((lo, hi)) => sweep.crop(lo, hi),
+                None => sweep,
+            };
+
+            match &mut decimator {
+                Some(decimator) => {
+                    if let Some(row) = decimator.push(sweep) {
+                        rows.push(&row);
+                    }
+                }
+                None => rows.push(&sweep),
+            }
+        }
+    }
+    if let Some(row) = decimator.as_mut().and_then(|d| d.flush()) {
+        rows.push(&row);
+    }
+
+    let (Some(width), Some(first), Some(last)) =
+        (rows.width, rows.timestamps.first(), rows.timestamps.last())
+    else {
+        return Err(eyre!("no sweeps left to render out of {} read", read));
+    };
+    info!(
+        "Rendering {} rows of {} pixels from {} sweeps",
+        rows.timestamps.len(),
+        width,
+        read
+    );
+
+    let img = RgbImage::from_raw(width as u32, rows.timestamps.len() as u32, rows.pixels)
+        .expect("row buffer does not match the image size");
+
+    let img = if args.bare {
+        img
+    } else {
+        let title = args.title.clone().unwrap_or_else(|| {
+            format!(
+                "{} - {} UTC, {:.3}-{:.3} MHz",
+                first.format("%Y-%m-%d %H:%M:%S"),
+                last.format("%Y-%m-%d %H:%M:%S"),
+                rows.hz_low as f64 / 1_000_000.0,
+                rows.hz_high as f64 / 1_000_000.0
+            )
+        });
+        let annotation = WaterfallAnnotation {
+            title: &title,
+            hz_low: rows.hz_low,
+            hz_high: rows.hz_high,
+            min_db: args.min_db,
+            max_db: args.max_db,
+        };
+        annotate(&img, 0, &annotation, &rows.timestamps, |db| {
+            let (r, g, b) = rows.colormap.eval_db(db, args.min_db, args.max_db);
+            Rgb([r, g, b])
+        })
+    };
+
+    img.save(&args.output)
+        .wrap_err_with(|| format!("unable to write {}", args.output.display()))?;
+    info!("Wrote {}", args.output.display());
+
+    Ok(())
+}
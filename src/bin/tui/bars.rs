@@ -0,0 +1,146 @@
+// Channel bars: the level of each channel of a plan, with a peak hold and
+// the share of recent sweeps it was busy in
+
+use std::collections::VecDeque;
+
+use hackrf_spectrum_analyzer::{
+    channels::{Channel, ChannelPlan},
+    sweep::Sweep,
+};
+
+/// Sweeps the occupancy of a channel is measured over
+const OCCUPANCY_SWEEPS: usize = 100;
+
+/// What is drawn for a channel
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelBar<'a> {
+    pub channel: &'a Channel,
+    /// Total power in the shown sweep, None if it does not cover the channel
+    pub power: Option<f32>,
+    /// Highest power since the last restart
+    pub hold: Option<f32>,
+    /// Fraction of the last `OCCUPANCY_SWEEPS` sweeps the channel was busy in
+    pub occupancy: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelBars {
+    plan: ChannelPlan,
+    holds: Vec<Option<f32>>,
+    /// Whether each channel was busy, in the sweeps covering it, oldest first
+    busy: Vec<VecDeque<bool>>,
+}
+
+impl ChannelBars {
+    pub fn new(plan: ChannelPlan) -> Self {
+        let holds = vec![None; plan.channels.len()];
+        let busy = vec![VecDeque::new(); plan.channels.len()];
+        Self { plan, holds, busy }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.plan.name
+    }
+
+    /// Raise the peak holds to the levels of the sweep and note which
+    /// channels have a bin above `threshold_db`
+    pub fn update(&mut self, sweep: &Sweep, threshold_db: f32) {
+        for ((channel, hold), busy) in self
+            .plan
+            .channels
+            .iter()
+            .zip(&mut self.holds)
+            .zip(&mut self.busy)
+        {
+            let Some(level) = channel.measure(sweep, threshold_db) else {
+                continue;
+            };
+            *hold = Some(hold.map_or(level.power, |held| held.max(level.power)));
+            busy.push_back(level.busy);
+            if busy.len() > OCCUPANCY_SWEEPS {
+                busy.pop_front();
+            }
+        }
+    }
+
+    /// Start the peak holds and the occupancy over
+    pub fn restart(&mut self) {
+        self.holds.fill(None);
+        self.busy.iter_mut().for_each(VecDeque::clear);
+    }
+
+    /// Each channel with its power in the sweep, peak hold and occupancy
+    pub fn bars<'a>(&'a self, sweep: &'a Sweep) -> impl Iterator<Item = ChannelBar<'a>> {
+        self.plan
+            .channels
+            .iter()
+            .zip(&self.holds)
+            .zip(&self.busy)
+            .map(move |((channel, &hold), busy)| ChannelBar {
+                channel,
+                power: sweep.band_power(channel.start_hz, channel.stop_hz),
+                hold,
+                occupancy: (!busy.is_empty())
+                    .then(|| busy.iter().filter(|&&b| b).count() as f32 / busy.len() as f32),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use hackrf_spectrum_analyzer::bandplan::BandPlan;
+
+    use super::*;
+
+    // Two 10 MHz channels over a sweep of 1 MHz bins
+    fn bars() -> ChannelBars {
+        let plan = BandPlan::parse("test", "A, 100, 110\nB, 110, 120\n").unwrap();
+        ChannelBars::new(plan.into())
+    }
+
+    fn sweep(a_db: f32, b_db: f32) -> Sweep {
+        let mut db = vec![a_db; 10];
+        db.extend([b_db; 10]);
+        Sweep {
+            hz_low: 100_000_000,
+            hz_high: 120_000_000,
+            hz_bin_width: 1_000_000.0,
+            db,
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn occupancy_is_the_share_of_busy_sweeps() {
+        let mut bars = bars();
+        for i in 0..4 {
+            let a = if i == 0 { -40.0 } else { -90.0 };
+            bars.update(&sweep(a, -40.0), -60.0);
+        }
+        let shown = sweep(-90.0, -90.0);
+        let occupancy: Vec<_> = bars.bars(&shown).map(|b| b.occupancy).collect();
+        assert_eq!(occupancy, [Some(0.25), Some(1.0)]);
+
+        // the peak hold keeps the loudest sweep, the power is the shown one
+        let a = bars.bars(&shown).next().unwrap();
+        assert!(a.hold.unwrap() > a.power.unwrap() + 40.0);
+    }
+
+    #[test]
+    fn occupancy_covers_the_last_sweeps_only() {
+        let mut bars = bars();
+        bars.update(&sweep(-40.0, -40.0), -60.0);
+        for _ in 0..OCCUPANCY_SWEEPS {
+            bars.update(&sweep(-90.0, -40.0), -60.0);
+        }
+        let shown = sweep(-90.0, -90.0);
+        let occupancy: Vec<_> = bars.bars(&shown).map(|b| b.occupancy).collect();
+        assert_eq!(occupancy, [Some(0.0), Some(1.0)]);
+
+        bars.restart();
+        assert!(bars
+            .bars(&shown)
+            .all(|b| b.occupancy.is_none() && b.hold.is_none()));
+    }
+}
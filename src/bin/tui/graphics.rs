@@ -0,0 +1,332 @@
+// Pixel images drawn over the character cells, with the Kitty graphics
+// protocol or Sixel
+
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Cursor, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ValueEnum;
+use hackrf_spectrum_analyzer::colormap::ColorMap;
+use image::{ImageFormat, RgbImage};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    buffer::Buffer,
+    crossterm::terminal,
+    layout::Rect,
+    style::Color,
+};
+
+/// Cell size assumed when the terminal does not report its size in pixels
+const DEFAULT_CELL: (u32, u32) = (8, 16);
+
+/// Base64 bytes sent per Kitty graphics escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// How the waterfall and spectrum are drawn
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GraphicsMode {
+    /// Pixels if the terminal looks to support them, character cells otherwise
+    Auto,
+    /// Kitty graphics protocol (kitty, WezTerm, Ghostty)
+    Kitty,
+    /// Sixel (foot, mlterm, xterm -ti vt340, ...)
+    Sixel,
+    /// Character cells only
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+}
+
+impl Protocol {
+    /// Guess the supported protocol from the environment
+    fn detect() -> Option<Self> {
+        // tmux and screen do not pass images through by default
+        if env::var_os("TMUX").is_some() || env::var_os("STY").is_some() {
+            return None;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || program == "WezTerm"
+            || program == "ghostty"
+        {
+            return Some(Self::Kitty);
+        }
+        if ["sixel", "foot", "mlterm", "yaft", "contour"]
+            .iter()
+            .any(|name| term.contains(name))
+        {
+            return Some(Self::Sixel);
+        }
+        None
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Kitty => "kitty",
+            Self::Sixel => "sixel",
+        }
+    }
+}
+
+/// An image of colour map levels, row by row from the top
+pub struct Pixels {
+    width: u32,
+    height: u32,
+    levels: Vec<u8>,
+}
+
+impl Pixels {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            levels: vec![0; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Set a pixel to a level between 0 and 1, y counted from the bottom
+    pub fn set(&mut self, x: u32, y: u32, value: f32) {
+        if x < self.width && y < self.height {
+            let index = (self.height - 1 - y) * self.width + x;
+            self.levels[index as usize] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Images waiting to be sent after the cells of a frame
+struct Placement {
+    area: Rect,
+    pixels: Pixels,
+    palette: Vec<(u8, u8, u8)>,
+}
+
+pub struct Graphics {
+    protocol: Protocol,
+    /// Pixels per cell
+    cell: (u32, u32),
+    queue: RefCell<Vec<Placement>>,
+    /// Areas covered by the last images, Sixel images stay until overwritten
+    shown: RefCell<Vec<Rect>>,
+    /// Whether anything was sent, to clean up on exit
+    used: Cell<bool>,
+}
+
+impl Graphics {
+    /// The pixel renderer selected on the command line, None for character cells
+    pub fn new(mode: GraphicsMode) -> Option<Self> {
+        let protocol = match mode {
+            GraphicsMode::Off => return None,
+            GraphicsMode::Auto => Protocol::detect()?,
+            GraphicsMode::Kitty => Protocol::Kitty,
+            GraphicsMode::Sixel => Protocol::Sixel,
+        };
+
+        let cell = terminal::window_size()
+            .ok()
+            .filter(|size| size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0)
+            .map(|size| {
+                (
+                    (size.width / size.columns) as u32,
+                    (size.height / size.rows) as u32,
+                )
+            });
+        // Sixel images are sized in pixels, a wrong guess would misplace them
+        let cell = match (cell, mode, protocol) {
+            (Some(cell), _, _) => cell,
+            (None, GraphicsMode::Auto, Protocol::Sixel) => return None,
+            (None, _, _) => DEFAULT_CELL,
+        };
+
+        Some(Self {
+            protocol,
+            cell,
+            queue: RefCell::new(Vec::new()),
+            shown: RefCell::new(Vec::new()),
+            used: Cell::new(false),
+        })
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Pixels per cell
+    pub fn cell_size(&self) -> (u32, u32) {
+        self.cell
+    }
+
+    /// An empty image covering the area
+    pub fn pixels(&self, area: Rect) -> Pixels {
+        Pixels::new(
+            area.width as u32 * self.cell.0,
+            area.height as u32 * self.cell.1,
+        )
+    }
+
+    /// Draw the image over the area once the cells of the frame are drawn
+    pub fn place(&self, area: Rect, pixels: Pixels, colormap: &ColorMap) {
+        let palette = (0..=255).map(|i| colormap.eval(i as f32 / 255.0)).collect();
+        self.queue.borrow_mut().push(Placement {
+            area,
+            pixels,
+            palette,
+        });
+    }
+
+    /// Send the images placed while drawing the frame. Returns true when
+    /// earlier Sixel images may still show, the screen then needs a full redraw.
+    pub fn flush(&self, buffer: &Buffer) -> io::Result<bool> {
+        let placements = self.queue.take();
+        let areas: Vec<Rect> = placements.iter().map(|p| p.area).collect();
+        let stale = self.protocol == Protocol::Sixel
+            && self.shown.borrow().iter().any(|area| !areas.contains(area));
+        *self.shown.borrow_mut() = areas;
+
+        let mut out = io::stdout().lock();
+        if self.protocol == Protocol::Kitty && self.used.get() {
+            // remove the images of the previous frame
+            out.write_all(b"\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        }
+        for (i, placement) in placements.iter().enumerate() {
+            let area = placement.area;
+            write!(out, "\x1b[{};{}H", area.y + 1, area.x + 1)?;
+            let data = match self.protocol {
+                Protocol::Kitty => kitty(placement, i as u32 + 1)?,
+                Protocol::Sixel => sixel(&placement.pixels, &placement.palette),
+            };
+            out.write_all(data.as_bytes())?;
+            self.used.set(true);
+        }
+
+        // Sixel images cover the text, draw the labels and markers again
+        if self.protocol == Protocol::Sixel && !placements.is_empty() {
+            let cells = placements.iter().flat_map(|placement| {
+                placement
+                    .area
+                    .positions()
+                    .map(|p| (p.x, p.y, &buffer[p]))
+                    .filter(|(_, _, cell)| cell.symbol() != " " || cell.bg != Color::Reset)
+            });
+            let mut backend = CrosstermBackend::new(&mut out);
+            backend.draw(cells)?;
+            Backend::flush(&mut backend)?;
+        }
+        out.flush()?;
+        Ok(stale)
+    }
+
+    /// Remove the Kitty images before leaving
+    pub fn clear(&self) -> io::Result<()> {
+        if self.protocol == Protocol::Kitty && self.used.get() {
+            let mut out = io::stdout().lock();
+            out.write_all(b"\x1b_Ga=d,d=a,q=2\x1b\\")?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// PNG transmitted in chunks, scaled to the area and drawn under the text
+fn kitty(placement: &Placement, id: u32) -> io::Result<String> {
+    let Pixels {
+        width,
+        height,
+        levels,
+    } = &placement.pixels;
+    let image = RgbImage::from_fn(*width, *height, |x, y| {
+        let (r, g, b) = placement.palette[levels[(y * width + x) as usize] as usize];
+        image::Rgb([r, g, b])
+    });
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    let data = STANDARD.encode(png);
+
+    let mut out = String::new();
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).expect("base64 is ascii");
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=100,i={},c={},r={},z=-1,C=1,q=2,m={};{}\x1b\\",
+                id, placement.area.width, placement.area.height, more, chunk
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    Ok(out)
+}
+
+// Six rows at a time, one pass per colour used in them
+fn sixel(pixels: &Pixels, palette: &[(u8, u8, u8)]) -> String {
+    let (width, height) = (pixels.width as usize, pixels.height as usize);
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for (i, &(r, g, b)) in palette.iter().enumerate() {
+        let percent = |c: u8| c as u32 * 100 / 255;
+        let _ = write!(out, "#{};2;{};{};{}", i, percent(r), percent(g), percent(b));
+    }
+
+    for top in (0..height).step_by(6) {
+        let rows = (height - top).min(6);
+        let mut used = [false; 256];
+        for y in top..top + rows {
+            for &level in &pixels.levels[y * width..(y + 1) * width] {
+                used[level as usize] = true;
+            }
+        }
+
+        for color in (0..256).filter(|&c| used[c]) {
+            let _ = write!(out, "#{}", color);
+            let mut run = (0u8, 0usize);
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|dy| pixels.levels[(top + dy) * width + x] as usize == color)
+                    .fold(0u8, |bits, dy| bits | 1 << dy);
+                if bits != run.0 && run.1 > 0 {
+                    push_run(&mut out, run);
+                    run.1 = 0;
+                }
+                run = (bits, run.1 + 1);
+            }
+            // nothing to draw after the last pixel of this colour
+            if run.0 != 0 {
+                push_run(&mut out, run);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_run(out: &mut String, (bits, count): (u8, usize)) {
+    let c = (63 + bits) as char;
+    if count > 3 {
+        let _ = write!(out, "!{}{}", count, c);
+    } else {
+        out.extend(std::iter::repeat_n(c, count));
+    }
+}
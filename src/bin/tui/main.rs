@@ -0,0 +1,2646 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::eyre, Result};
+use ratatui::{
+    crossterm::{
+        event::{
+            self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseButton, MouseEvent,
+            MouseEventKind,
+        },
+        execute,
+    },
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    symbols,
+    text::{Line, Span},
+    widgets::{
+        canvas::{Canvas, Rectangle},
+        Axis, Block, Chart, Dataset, GraphType, LegendPosition,
+    },
+    DefaultTerminal, Frame,
+};
+
+use clap::Parser;
+use hackrf_spectrum_analyzer::{
+    allocation::AllocationDb,
+    autoscale::AutoScale,
+    bandplan::BandPlan,
+    channels::ChannelPlan,
+    colormap::ColorMap,
+    peaks::{find_peaks, noise_floor, Peak},
+    run_sweep,
+    sweep::{DownscaleMode, Sweep},
+    ticks::{decimals_for_step, nice_step, nice_ticks, nice_time_step},
+    SweepParams,
+};
+
+mod bars;
+mod graphics;
+mod markers;
+mod palette;
+mod persistence;
+mod power;
+mod settings;
+mod snapshot;
+mod timing;
+mod traces;
+mod tuning;
+
+use bars::ChannelBars;
+use graphics::{Graphics, GraphicsMode};
+use markers::{level, next_peak, peak_hz, Markers};
+use palette::{ascii_shade, ColorDepth};
+use persistence::Persistence;
+use power::{BandPower, PowerWindow};
+use settings::{SettingsPanel, SETTINGS};
+use timing::{RowEvent, RowEvents, SweepStats};
+use traces::{TraceKind, Traces};
+use tuning::{SweepSource, Tuning, Zoom};
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(flatten)]
+    sweep: SweepParams,
+
+    /// Colours used to draw, detected from COLORTERM and TERM by default
+    #[arg(long, value_enum)]
+    colors: Option<ColorDepth>,
+
+    /// Fraction of the persistence display kept from one sweep to the next, 0.5-0.995
+    #[arg(long, default_value_t = 0.95)]
+    persistence_decay: f32,
+
+    /// Number of sweeps kept to scroll back through while paused
+    #[arg(long, default_value_t = 1000)]
+    history: usize,
+
+    /// Directory the waterfall is dumped to with the w key
+    #[arg(long, default_value = ".")]
+    dump_dir: PathBuf,
+
+    /// Draw the waterfall and spectrum as pixels with the Kitty graphics protocol or Sixel
+    #[arg(long, value_enum, default_value_t = GraphicsMode::Off)]
+    graphics: GraphicsMode,
+
+    /// Level in dB to draw a horizontal line at in the trace view; can be repeated
+    #[arg(long = "threshold", allow_negative_numbers = true)]
+    thresholds: Vec<f32>,
+
+    /// Reference to start with, a .sweeps file saved with W or dumped with w (averaged)
+    #[arg(long)]
+    reference: Option<PathBuf>,
+
+    /// Rise above the reference in dB highlighted in the difference display
+    #[arg(long, default_value_t = 6.0)]
+    reference_margin: f32,
+
+    /// Frequency window "MIN-MAX" in MHz whose power is charted over time; can be repeated
+    #[arg(long = "band-power", value_parser = power::parse_window)]
+    band_power: Vec<(u64, u64)>,
+
+    /// Channel plan of the channel bars, either a built-in (wifi24, ble) or a band plan file path
+    #[arg(long)]
+    channels: Option<String>,
+}
+
+// Number of peaks listed in the header
+const MAX_PEAKS: usize = 4;
+
+// Colours of markers 1 to 4
+const MARKER_COLORS: [Color; markers::MAX_MARKERS] = [
+    Color::LightYellow,
+    Color::LightCyan,
+    Color::LightMagenta,
+    Color::LightGreen,
+];
+
+// Columns moved by the marker keys, without and with shift
+const MARKER_STEP: u64 = 1;
+const MARKER_BIG_STEP: u64 = 10;
+
+// Rows between the dB labels, at least
+const DB_LABEL_ROWS: u16 = 2;
+
+// Columns between two frequency labels, at least
+const FREQ_LABEL_GAP: u16 = 2;
+
+// Columns between the time labels of the band power charts, at least
+const TIME_LABEL_COLUMNS: u16 = 10;
+
+// Rows between the time labels of the waterfall
+const TIME_LABEL_ROWS: usize = 4;
+
+// Share of the height given to the spectrum in split mode, in percent
+const SPLIT_DEFAULT: u16 = 40;
+const SPLIT_STEP: u16 = 10;
+const SPLIT_LIMITS: (u16, u16) = (20, 80);
+
+// Zoom factor of one key press or scroll step
+const ZOOM_STEP: f64 = 2.0;
+
+// Fraction of the span moved by one pan key press
+const PAN_STEP: f64 = 0.25;
+
+// Event type for our application
+enum AppEvent {
+    Key(KeyCode),
+    Mouse(MouseEvent),
+    /// A sweep, the generation of the settings it was made with and the time
+    /// hackrf_sweep took
+    NewSweep(u64, Sweep, Duration),
+    Quit,
+}
+
+// Frozen waterfall, scrolled through while the sweeps keep coming in
+struct Frozen {
+    /// History index of the newest sweep shown
+    offset: usize,
+    /// History index of the selected sweep
+    cursor: usize,
+    /// Automatic dB range when paused
+    auto_range: Option<(f32, f32)>,
+    /// Traces as they were when paused
+    traces: Traces,
+    /// Channel holds and occupancy as they were when paused
+    bars: Option<ChannelBars>,
+}
+
+// Visualization mode
+enum VisualizationMode {
+    Spectrum,
+    Waterfall,
+    /// Spectrum on top of the waterfall
+    Split,
+    /// Power of the band power windows over time
+    Power,
+    /// Channel bars on top of the waterfall
+    Channels,
+}
+
+// Files read before taking over the terminal
+struct Loaded {
+    band_plans: Vec<BandPlan>,
+    allocations: AllocationDb,
+    colormap: ColorMap,
+    reference: Option<Sweep>,
+    channel_plan: Option<ChannelPlan>,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    color_eyre::install()?;
+
+    let args = Args::parse();
+    let params = args.sweep.clone();
+    if args.band_power.len() > power::MAX_WINDOWS {
+        return Err(eyre!("at most {} --band-power windows", power::MAX_WINDOWS));
+    }
+
+    // Load band plans before taking over the terminal so errors are readable
+    let loaded = Loaded {
+        band_plans: BandPlan::load_all(&params.band_plans)?,
+        colormap: params.load_colormap()?,
+        allocations: match &params.allocations {
+            Some(path) => AllocationDb::from_file(path)?,
+            None => AllocationDb::default(),
+        },
+        reference: args
+            .reference
+            .as_deref()
+            .map(snapshot::load_reference)
+            .transpose()?,
+        channel_plan: args
+            .channels
+            .as_deref()
+            .map(ChannelPlan::load)
+            .transpose()?,
+    };
+
+    // Initialize the terminal
+    let terminal = ratatui::init();
+    execute!(std::io::stdout(), EnableMouseCapture)?;
+
+    // Create channel for events
+    let (event_tx, event_rx) = channel();
+
+    // Clone the transmitter for the sweep thread
+    let sweep_tx = event_tx.clone();
+
+    // Settings of the sweep thread, changed by the app when retuning
+    let source = SweepSource::shared(params.clone());
+
+    // Start the sweep thread
+    let sweep_thread = {
+        let source = source.clone();
+        thread::spawn(move || {
+            loop {
+                let SweepSource { params, generation } =
+                    source.lock().expect("sweep source poisoned").clone();
+                let started = Instant::now();
+                let sw = run_sweep(&params);
+                let latency = started.elapsed();
+                if sweep_tx
+                    .send(AppEvent::NewSweep(generation, sw, latency))
+                    .is_err()
+                {
+                    break;
+                }
+                // No sleep here - run as fast as possible
+            }
+        })
+    };
+
+    // Start the event handling thread
+    let event_thread = {
+        let event_tx = event_tx.clone();
+        thread::spawn(move || {
+            loop {
+                // Poll for events
+                if let Ok(true) = event::poll(Duration::from_millis(100)) {
+                    match event::read() {
+                        Ok(Event::Key(key)) => {
+                            if key.code == KeyCode::Char('q') {
+                                let _ = event_tx.send(AppEvent::Quit);
+                                break;
+                            } else {
+                                let _ = event_tx.send(AppEvent::Key(key.code));
+                            }
+                        }
+                        Ok(Event::Mouse(mouse)) => {
+                            let _ = event_tx.send(AppEvent::Mouse(mouse));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        })
+    };
+
+    // Run the app
+    let app_result = App::new(event_rx, source, args, loaded).run(terminal);
+
+    // Clean up
+    let _ = execute!(std::io::stdout(), DisableMouseCapture);
+    ratatui::restore();
+
+    // Wait for threads to finish
+    let _ = sweep_thread.join();
+    let _ = event_thread.join();
+
+    app_result
+}
+
+struct App {
+    event_receiver: Receiver<AppEvent>,
+    /// Settings read by the sweep thread
+    source: Arc<Mutex<SweepSource>>,
+    /// Sweeps made with older settings are dropped
+    generation: u64,
+    current_sweep: Option<Sweep>,
+    params: SweepParams,
+    zoom: Zoom,
+    /// Area of the spectrum or waterfall, as last drawn, for the mouse
+    chart_area: Cell<Rect>,
+    /// Columns of a mouse drag in progress, from the start column to the current one
+    selection: Option<(u16, u16)>,
+    markers: Markers,
+    /// Settings panel, when open
+    settings: Option<SettingsPanel>,
+    persistence: Persistence,
+    /// Show the persistence instead of the density of the last sweep
+    show_persistence: bool,
+    /// Draw the spectrum as line traces on axes instead of the density
+    show_traces: bool,
+    traces: Traces,
+    /// Width of the dB axis of the trace chart, as last drawn
+    trace_gutter: Cell<u16>,
+    /// Levels of the horizontal lines of the trace view
+    thresholds: Vec<f32>,
+    /// Show the sweeps less the reference in the trace view and waterfall
+    show_difference: bool,
+    /// Rise above the reference highlighted in the difference display, in dB
+    reference_margin: f32,
+    band_power: BandPower,
+    /// Channel plan of the channel bars, with the peak holds
+    channel_bars: Option<ChannelBars>,
+    /// Set while paused
+    frozen: Option<Frozen>,
+    /// Sweeps shown by the waterfall, as last drawn, to page through the history
+    waterfall_rows: Cell<usize>,
+    dump_dir: PathBuf,
+    /// Outcome of the last action, shown in the header
+    status: Option<String>,
+    stats: SweepStats,
+    row_events: RowEvents,
+    /// Settings change to mark on the next sweep
+    pending_change: Option<String>,
+    /// Label the waterfall rows with the time of day instead of the age
+    absolute_time: bool,
+    data_points: Vec<(f64, f64)>,
+    waterfall_history: VecDeque<Sweep>,
+    visualization_mode: VisualizationMode,
+    /// Percentage of the height used by the spectrum in split mode
+    split_ratio: u16,
+    /// Area of the spectrum, as last drawn, empty when not shown
+    spectrum_area: Cell<Rect>,
+    max_waterfall_lines: usize,
+    downscale_mode: DownscaleMode,
+    band_plans: Vec<BandPlan>,
+    allocations: AllocationDb,
+    peaks: Vec<Peak>,
+    colormap: ColorMap,
+    /// Automatic dB range, `None` when the manual --min-db/--max-db range is used
+    autoscale: Option<AutoScale>,
+    color_depth: ColorDepth,
+    /// Pixel renderer, character cells when None
+    graphics: Option<Graphics>,
+}
+
+impl App {
+    fn new(
+        event_receiver: Receiver<AppEvent>,
+        source: Arc<Mutex<SweepSource>>,
+        args: Args,
+        loaded: Loaded,
+    ) -> Self {
+        let params = args.sweep;
+        let mut traces = Traces::default();
+        if let Some(reference) = loaded.reference {
+            traces.set_reference(reference);
+        }
+        Self {
+            event_receiver,
+            source,
+            generation: 0,
+            current_sweep: None,
+            zoom: Zoom::new(Tuning::of(&params)),
+            chart_area: Cell::new(Rect::default()),
+            selection: None,
+            markers: Markers::default(),
+            settings: None,
+            persistence: Persistence::new(args.persistence_decay),
+            show_persistence: false,
+            show_traces: false,
+            traces,
+            trace_gutter: Cell::new(0),
+            thresholds: args.thresholds,
+            show_difference: false,
+            reference_margin: args.reference_margin.max(1.0),
+            band_power: BandPower::new(&args.band_power, args.history.max(1)),
+            channel_bars: loaded.channel_plan.map(ChannelBars::new),
+            frozen: None,
+            waterfall_rows: Cell::new(0),
+            dump_dir: args.dump_dir,
+            status: None,
+            stats: SweepStats::default(),
+            row_events: RowEvents::default(),
+            pending_change: None,
+            absolute_time: false,
+            data_points: Vec::new(),
+            waterfall_history: VecDeque::new(),
+            visualization_mode: VisualizationMode::Waterfall,
+            split_ratio: SPLIT_DEFAULT,
+            spectrum_area: Cell::new(Rect::default()),
+            max_waterfall_lines: args.history.max(1),
+            downscale_mode: DownscaleMode::Peak,
+            band_plans: loaded.band_plans,
+            allocations: loaded.allocations,
+            peaks: Vec::new(),
+            colormap: loaded.colormap,
+            autoscale: params.autoscale(),
+            params,
+            color_depth: args.colors.unwrap_or_else(ColorDepth::detect),
+            graphics: Graphics::new(args.graphics),
+        }
+    }
+
+    fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
+        // Initial draw
+        self.redraw(&mut terminal)?;
+
+        loop {
+            match self.event_receiver.recv() {
+                Ok(AppEvent::Key(key)) => {
+                    if self.handle_settings_key(key) {
+                        self.redraw(&mut terminal)?;
+                        continue;
+                    }
+                    match key {
+                        KeyCode::Char('r') => {
+                            // Reset data
+                            self.waterfall_history.clear();
+                            self.row_events.clear();
+                            self.frozen = None;
+                            self.persistence.clear();
+                            self.traces.restart();
+                            self.band_power.clear();
+                            if let Some(bars) = &mut self.channel_bars {
+                                bars.restart();
+                            }
+                            self.current_sweep = None;
+                            self.update_spectrum();
+                            if let Some(autoscale) = &mut self.autoscale {
+                                autoscale.reset();
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('m') => {
+                            // Cycle through the visualization modes
+                            self.visualization_mode = match self.visualization_mode {
+                                VisualizationMode::Spectrum => VisualizationMode::Power,
+                                VisualizationMode::Power => VisualizationMode::Channels,
+                                VisualizationMode::Channels => VisualizationMode::Waterfall,
+                                VisualizationMode::Waterfall => VisualizationMode::Split,
+                                VisualizationMode::Split => VisualizationMode::Spectrum,
+                            };
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char(' ') => {
+                            // Pause/resume the display, sweeps are still recorded
+                            if self.frozen.take().is_some() {
+                                self.current_sweep = self.waterfall_history.front().cloned();
+                                self.update_spectrum();
+                            } else if !self.waterfall_history.is_empty() {
+                                self.frozen = Some(Frozen {
+                                    offset: 0,
+                                    cursor: 0,
+                                    auto_range: self.autoscale.as_ref().and_then(|a| a.range()),
+                                    traces: self.traces.clone(),
+                                    bars: self.channel_bars.clone(),
+                                });
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => {
+                            // Move the cursor to older/newer sweeps while paused
+                            let page = self.waterfall_rows.get().max(1) as isize;
+                            let rows = match key {
+                                KeyCode::Up => 1,
+                                KeyCode::Down => -1,
+                                KeyCode::PageUp => page,
+                                _ => -page,
+                            };
+                            self.scroll(rows);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('w') => {
+                            self.status = Some(match self.dump() {
+                                Ok(path) => format!("Saved {}", path.display()),
+                                Err(e) => format!("Dump failed: {}", e),
+                            });
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('T') => {
+                            // Age or time of day on the waterfall rows
+                            self.absolute_time = !self.absolute_time;
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('P') => {
+                            // Toggle the persistence display
+                            self.show_persistence = !self.show_persistence;
+                            self.persistence.clear();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('l') => {
+                            // Line traces or density
+                            self.show_traces = !self.show_traces;
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('h') => {
+                            self.traces.toggle_max_hold();
+                            if let Some(frozen) = &mut self.frozen {
+                                frozen.traces.toggle_max_hold();
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('v') => {
+                            self.traces.toggle_average();
+                            if let Some(frozen) = &mut self.frozen {
+                                frozen.traces.toggle_average();
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('R') => {
+                            // Keep the shown sweep, or the average while it is on, as reference
+                            let sweep = match &self.frozen {
+                                None => self.traces.mean().or(self.current_sweep.as_ref()),
+                                Some(_) => self.current_sweep.as_ref(),
+                            }
+                            .cloned();
+                            self.traces.toggle_reference(sweep.as_ref());
+                            if let Some(frozen) = &mut self.frozen {
+                                frozen.traces.toggle_reference(sweep.as_ref());
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('W') => {
+                            self.status = Some(match self.traces.reference() {
+                                Some(reference) => {
+                                    match snapshot::save_reference(&self.dump_dir, reference) {
+                                        Ok(path) => format!("Saved {}", path.display()),
+                                        Err(e) => format!("Saving the reference failed: {}", e),
+                                    }
+                                }
+                                None => "No reference to save, R keeps one".to_string(),
+                            });
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('D') => {
+                            // Difference to the reference in the traces and waterfall
+                            self.show_difference = !self.show_difference;
+                            if self.show_difference && self.traces.reference().is_none() {
+                                self.status = Some("No reference, R keeps one".to_string());
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('{') | KeyCode::Char('}') => {
+                            // Shorter/longer persistence
+                            let factor = if key == KeyCode::Char('{') { 0.5 } else { 2.0 };
+                            self.persistence.scale_decay(factor);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('[') | KeyCode::Char(']') => {
+                            // Smaller/larger spectrum in split mode
+                            self.split_ratio = if key == KeyCode::Char('[') {
+                                self.split_ratio - SPLIT_STEP
+                            } else {
+                                self.split_ratio + SPLIT_STEP
+                            }
+                            .clamp(SPLIT_LIMITS.0, SPLIT_LIMITS.1);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('d') => {
+                            // Toggle downscale mode
+                            self.downscale_mode = match self.downscale_mode {
+                                DownscaleMode::Peak => DownscaleMode::Average,
+                                DownscaleMode::Average => DownscaleMode::Peak,
+                            };
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('c') => {
+                            self.colormap = self.colormap.next_builtin();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('C') => {
+                            self.colormap = self.colormap.previous_builtin();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('a') => {
+                            // Toggle automatic/manual dB range
+                            self.params.auto_db = !self.params.auto_db;
+                            self.autoscale = self.params.autoscale();
+                            if let (Some(autoscale), Some(sweep)) =
+                                (&mut self.autoscale, &self.current_sweep)
+                            {
+                                autoscale.update(sweep);
+                            }
+                            if let Some(frozen) = &mut self.frozen {
+                                frozen.auto_range = self.autoscale.as_ref().and_then(|a| a.range());
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('g') | KeyCode::Char('G') => {
+                            // Gamma down/up
+                            let step = if key == KeyCode::Char('g') { -0.1 } else { 0.1 };
+                            self.colormap.gamma = (self.colormap.gamma + step).clamp(0.1, 5.0);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('k') | KeyCode::Char('K') => {
+                            // Contrast down/up
+                            let step = if key == KeyCode::Char('k') { -0.1 } else { 0.1 };
+                            self.colormap.contrast =
+                                (self.colormap.contrast + step).clamp(0.1, 5.0);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char('-') => {
+                            // Zoom in/out around the center
+                            let current = Tuning::of(&self.params);
+                            let factor = if key == KeyCode::Char('-') {
+                                1.0 / ZOOM_STEP
+                            } else {
+                                ZOOM_STEP
+                            };
+                            let tuning = self.zoom.zoom(current, factor, current.center());
+                            self.retune(tuning);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Left | KeyCode::Right => {
+                            // Pan
+                            let step = if key == KeyCode::Left {
+                                -PAN_STEP
+                            } else {
+                                PAN_STEP
+                            };
+                            let tuning = self.zoom.pan(Tuning::of(&self.params), step);
+                            self.retune(tuning);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Backspace => {
+                            // Back to the range before the last zoom
+                            if let Some(tuning) = self.zoom.back() {
+                                self.retune(tuning);
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('n') => {
+                            // New marker on the strongest bin
+                            let hz = self
+                                .current_sweep
+                                .as_ref()
+                                .and_then(peak_hz)
+                                .unwrap_or((self.hz_low() + self.hz_high()) / 2);
+                            self.markers.add(hz);
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('x') => {
+                            self.markers.remove_active();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('b') => {
+                            // Follow the power between the outermost markers
+                            let low = self.markers.iter().map(|m| m.hz).min();
+                            let high = self.markers.iter().map(|m| m.hz).max();
+                            self.status = Some(match (low, high) {
+                                (Some(low), Some(high)) if high > low => {
+                                    if self.band_power.add(low, high) {
+                                        format!(
+                                            "Band power of {:.3}-{:.3} MHz",
+                                            low as f64 / 1_000_000.0,
+                                            high as f64 / 1_000_000.0
+                                        )
+                                    } else {
+                                        format!("At most {} band power windows", power::MAX_WINDOWS)
+                                    }
+                                }
+                                _ => "Two markers bound a band power window".to_string(),
+                            });
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('B') => {
+                            self.band_power.remove_last();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Tab => {
+                            self.markers.select_next();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char(',')
+                        | KeyCode::Char('.')
+                        | KeyCode::Char('<')
+                        | KeyCode::Char('>') => {
+                            // Move the active marker by one or ten columns
+                            let columns = match key {
+                                KeyCode::Char(',') | KeyCode::Char('.') => MARKER_STEP,
+                                _ => MARKER_BIG_STEP,
+                            };
+                            let step = self.marker_step() * columns;
+                            let left = matches!(key, KeyCode::Char(',') | KeyCode::Char('<'));
+                            let (hz_low, hz_high) = (self.hz_low(), self.hz_high());
+                            if let Some(marker) = self.markers.active_mut() {
+                                marker.tracking = false;
+                                marker.hz = if left {
+                                    marker.hz.saturating_sub(step).max(hz_low)
+                                } else {
+                                    (marker.hz + step).min(hz_high)
+                                };
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('p') | KeyCode::Char('N') => {
+                            // Peak search, next lower peak
+                            if let Some(sweep) = &self.current_sweep {
+                                if let Some(marker) = self.markers.active_mut() {
+                                    let hz = if key == KeyCode::Char('p') {
+                                        peak_hz(sweep)
+                                    } else {
+                                        next_peak(sweep, marker.hz)
+                                    };
+                                    if let Some(hz) = hz {
+                                        marker.hz = hz;
+                                        marker.tracking = false;
+                                    }
+                                }
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('t') => {
+                            // Follow the maximum
+                            if let Some(marker) = self.markers.active_mut() {
+                                marker.tracking = !marker.tracking;
+                            }
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('o') => {
+                            // Measure the other markers from the active one
+                            self.markers.toggle_reference();
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('s') => {
+                            // Open the settings panel
+                            self.settings = Some(SettingsPanel::default());
+                            self.redraw(&mut terminal)?;
+                        }
+                        KeyCode::Char('f') | KeyCode::Home => {
+                            // Full span
+                            let tuning = self.zoom.full(Tuning::of(&self.params));
+                            self.retune(tuning);
+                            self.redraw(&mut terminal)?;
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(AppEvent::Mouse(mouse)) => {
+                    self.handle_mouse(mouse);
+                    self.redraw(&mut terminal)?;
+                }
+                Ok(AppEvent::NewSweep(generation, sweep, latency)) => {
+                    // made before the last retune
+                    if generation != self.generation {
+                        self.stats.dropped += 1;
+                        continue;
+                    }
+                    self.update_data(sweep, latency);
+                    self.redraw(&mut terminal)?;
+                }
+                Ok(AppEvent::Quit) => {
+                    break;
+                }
+                Err(_) => {
+                    // Channel closed
+                    break;
+                }
+            }
+        }
+
+        if let Some(graphics) = &self.graphics {
+            graphics.clear()?;
+        }
+        Ok(())
+    }
+
+    // Draw the cells, then the images placed while drawing them
+    fn redraw(&self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let frame = terminal.draw(|frame| self.draw(frame))?;
+        let Some(graphics) = &self.graphics else {
+            return Ok(());
+        };
+        if graphics.flush(frame.buffer)? {
+            // an image went away, draw everything again over it
+            terminal.clear()?;
+            let frame = terminal.draw(|frame| self.draw(frame))?;
+            graphics.flush(frame.buffer)?;
+        }
+        Ok(())
+    }
+
+    fn update_data(&mut self, sweep: Sweep, latency: Duration) {
+        // hackrf_sweep rounds the range up to whole tuning steps
+        let sweep = sweep.crop(self.hz_low(), self.hz_high());
+        if sweep.db.is_empty() {
+            return;
+        }
+
+        if let Some(seconds) = self.stats.record(sweep.timestamp, latency) {
+            self.row_events
+                .push(sweep.timestamp, RowEvent::Gap(seconds));
+        }
+        if let Some(change) = self.pending_change.take() {
+            self.row_events
+                .push(sweep.timestamp, RowEvent::Changed(change));
+        }
+        self.band_power.update(&sweep);
+        if let Some(autoscale) = &mut self.autoscale {
+            autoscale.update(&sweep);
+        }
+        self.markers.track(&sweep);
+        self.traces.update(&sweep);
+        if let Some(bars) = &mut self.channel_bars {
+            // a bin rising above the noise like a peak makes a channel busy
+            bars.update(&sweep, noise_floor(&sweep.db) + self.params.peak_threshold);
+        }
+
+        // Only record while paused, the frozen view stays on the same sweeps
+        // and what was accumulated up to the pause
+        if self.frozen.is_some() {
+            self.waterfall_history.push_front(sweep);
+            self.waterfall_history.truncate(self.max_waterfall_lines);
+            self.trim_row_events();
+            let frozen = self.frozen.as_mut().unwrap();
+            let last = self.waterfall_history.len() - 1;
+            frozen.offset = (frozen.offset + 1).min(last);
+            frozen.cursor = (frozen.cursor + 1).min(last);
+            return;
+        }
+
+        // Update waterfall history
+        self.waterfall_history.push_front(sweep.clone());
+        if self.waterfall_history.len() > self.max_waterfall_lines {
+            self.waterfall_history.pop_back();
+        }
+        self.trim_row_events();
+
+        self.current_sweep = Some(sweep);
+        self.update_spectrum();
+
+        // sized like the spectrum as last drawn
+        let area = self.spectrum_area.get();
+        if self.show_persistence && area.width > 0 && area.height > 0 {
+            let (width, height) = (area.width as usize, area.height as usize);
+            let cells = self.spectrum_cells(width, height);
+            self.persistence.accumulate(width, height, cells);
+        }
+    }
+
+    fn trim_row_events(&mut self) {
+        if let Some(oldest) = self.waterfall_history.back() {
+            self.row_events.trim(oldest.timestamp);
+        }
+    }
+
+    /// Move the cursor of the frozen view by a number of rows, positive to older sweeps
+    fn scroll(&mut self, rows: isize) {
+        let Some(frozen) = &mut self.frozen else {
+            return;
+        };
+        let last = self.waterfall_history.len().saturating_sub(1);
+        frozen.cursor = frozen.cursor.saturating_add_signed(rows).min(last);
+
+        // keep the cursor in view
+        let height = self.waterfall_rows.get().max(1);
+        if frozen.cursor < frozen.offset {
+            frozen.offset = frozen.cursor;
+        } else if frozen.cursor >= frozen.offset + height {
+            frozen.offset = frozen.cursor + 1 - height;
+        }
+
+        // the spectrum and markers show the selected sweep
+        self.current_sweep = self.waterfall_history.get(frozen.cursor).cloned();
+        self.update_spectrum();
+    }
+
+    /// History index of the newest sweep shown in the waterfall
+    fn view_offset(&self) -> usize {
+        self.frozen.as_ref().map_or(0, |f| f.offset)
+    }
+
+    /// Save the sweeps shown in the waterfall
+    fn dump(&self) -> Result<PathBuf> {
+        let rows = self.waterfall_rows.get().max(1);
+        let sweeps: Vec<&Sweep> = self
+            .waterfall_history
+            .iter()
+            .skip(self.view_offset())
+            .take(rows)
+            .rev()
+            .collect();
+        snapshot::dump(
+            &self.dump_dir,
+            &sweeps,
+            &self.colormap,
+            self.db_range(),
+            &self.params.summary(),
+        )
+    }
+
+    // Cell of the spectrum grid of each data point, x from the left and y from the bottom
+    fn spectrum_cells(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let (hz_low, hz_high) = self.shown_span();
+        let (min_freq, max_freq) = (hz_low as f64 / 1_000_000.0, hz_high as f64 / 1_000_000.0);
+        let (min_db, max_db) = self.db_range();
+        let (min_db, max_db) = (min_db as f64, max_db as f64);
+
+        self.data_points
+            .iter()
+            .filter_map(|&(freq, db)| {
+                // Calculate x position (frequency)
+                let x_pos =
+                    ((freq - min_freq) / (max_freq - min_freq) * (width as f64 - 1.0)) as usize;
+
+                // Calculate y position (power)
+                let y_normalized = (db - min_db) / (max_db - min_db);
+                let y_normalized = y_normalized.clamp(0.0, 1.0);
+                // Use y_normalized directly without inverting
+                let y_pos = (height as f64 * y_normalized) as usize;
+
+                // Ensure we're within bounds
+                (x_pos < width && y_pos < height && x_pos > 0 && y_pos > 0)
+                    .then_some((x_pos, y_pos))
+            })
+            .collect()
+    }
+
+    // Spectrum data points and peaks of the current sweep
+    fn update_spectrum(&mut self) {
+        self.data_points.clear();
+        self.peaks.clear();
+        let Some(sweep) = &self.current_sweep else {
+            return;
+        };
+
+        for (i, db) in sweep.db.iter().enumerate() {
+            let freq = sweep.bin_hz(i) as f64 / 1_000_000.0;
+            self.data_points.push((freq, *db as f64));
+        }
+
+        // Keep peaks at least a few bins apart so one signal is not listed twice
+        let separation = (sweep.hz_bin_width * 5.0) as u64;
+        self.peaks = find_peaks(sweep, self.params.peak_threshold, separation, MAX_PEAKS);
+    }
+
+    /// Frequency range across the chart: that of the sweeps shown, the tuned one before the first
+    fn shown_span(&self) -> (u64, u64) {
+        self.current_sweep
+            .as_ref()
+            .or(self.waterfall_history.front())
+            .map_or((self.hz_low(), self.hz_high()), |s| (s.hz_low, s.hz_high))
+    }
+
+    fn hz_low(&self) -> u64 {
+        self.params.min_freq as u64 * 1_000_000
+    }
+
+    fn hz_high(&self) -> u64 {
+        self.params.max_freq as u64 * 1_000_000
+    }
+
+    /// Sweep a new range: the sweep thread picks it up on its next sweep
+    fn retune(&mut self, tuning: Tuning) {
+        if tuning == Tuning::of(&self.params) {
+            return;
+        }
+        tuning.apply(&mut self.params);
+        self.restart_sweep(format!(
+            "{}-{} MHz",
+            self.params.min_freq, self.params.max_freq
+        ));
+        self.frozen = None;
+
+        self.persistence.clear();
+        self.traces.restart();
+        if let Some(bars) = &mut self.channel_bars {
+            bars.restart();
+        }
+
+        // Keep the history when zooming into it, a new range has no history yet
+        let (hz_low, hz_high) = (self.hz_low(), self.hz_high());
+        if self
+            .waterfall_history
+            .iter()
+            .all(|s| s.hz_low <= hz_low && s.hz_high >= hz_high)
+        {
+            for sweep in self.waterfall_history.iter_mut() {
+                *sweep = sweep.crop(hz_low, hz_high);
+            }
+        } else {
+            self.waterfall_history.clear();
+        }
+        self.waterfall_history.retain(|s| !s.db.is_empty());
+
+        // the noise floor depends on the bin width
+        if let Some(autoscale) = &mut self.autoscale {
+            autoscale.reset();
+        }
+
+        self.current_sweep = self.waterfall_history.front().cloned();
+        self.update_spectrum();
+    }
+
+    /// Hand the settings to the sweep thread, sweeps still running with the
+    /// old ones are dropped
+    fn restart_sweep(&mut self, change: String) {
+        self.pending_change = Some(change);
+        self.stats.restart();
+        self.generation += 1;
+        let mut source = self.source.lock().expect("sweep source poisoned");
+        source.params = self.params.clone();
+        source.generation = self.generation;
+    }
+
+    // Keys of the open settings panel, false for the keys it does not use
+    fn handle_settings_key(&mut self, key: KeyCode) -> bool {
+        let Some(panel) = &mut self.settings else {
+            return false;
+        };
+        let setting = panel.setting();
+
+        match key {
+            KeyCode::Up | KeyCode::Down => panel.select(key == KeyCode::Down),
+            KeyCode::Esc | KeyCode::Char('s') | KeyCode::Enter => self.settings = None,
+            KeyCode::Left | KeyCode::Right | KeyCode::Char('-') | KeyCode::Char('+') => {
+                let up = matches!(key, KeyCode::Right | KeyCode::Char('+'));
+                if !setting.restarts_sweep() && self.autoscale.is_some() {
+                    // editing the range switches to it, starting from the automatic one
+                    (self.params.min_db, self.params.max_db) = self.db_range();
+                    self.params.auto_db = false;
+                    self.autoscale = None;
+                }
+                setting.adjust(&mut self.params, up);
+                if setting.restarts_sweep() {
+                    self.zoom.set_resolution(Tuning::of(&self.params));
+                    // the noise floor depends on the gains and the bin width
+                    if let Some(autoscale) = &mut self.autoscale {
+                        autoscale.reset();
+                    }
+                    self.restart_sweep(format!(
+                        "{} {}",
+                        setting.label(),
+                        setting.value(&self.params)
+                    ));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Frequency under a terminal column of the chart
+    fn column_hz(&self, column: u16) -> u64 {
+        (self.column_mhz(column) * 1_000_000.0) as u64
+    }
+
+    // Terminal column of the chart showing a frequency
+    fn hz_column(&self, hz: u64) -> Option<u16> {
+        let area = self.chart_area.get();
+        let (hz_low, hz_high) = self.shown_span();
+        if hz < hz_low || hz > hz_high || hz_high == hz_low || area.width == 0 {
+            return None;
+        }
+        let position = (hz - hz_low) as f64 / (hz_high - hz_low) as f64;
+        let x = ((position * area.width as f64) as u16).min(area.width - 1);
+        Some(area.x + x)
+    }
+
+    // Marker movement of one column, at least a bin
+    fn marker_step(&self) -> u64 {
+        let width = self.chart_area.get().width.max(1) as u64;
+        let bin = self
+            .current_sweep
+            .as_ref()
+            .map_or(1, |s| s.hz_bin_width as u64);
+        ((self.hz_high() - self.hz_low()) / width).max(bin)
+    }
+
+    // Frequency in MHz under a terminal column of the chart
+    fn column_mhz(&self, column: u16) -> f64 {
+        let area = self.chart_area.get();
+        let x = column
+            .saturating_sub(area.x)
+            .min(area.width.saturating_sub(1));
+        let (hz_low, hz_high) = self.shown_span();
+        let span = (hz_high - hz_low) as f64 / 1_000_000.0;
+        hz_low as f64 / 1_000_000.0 + (x as f64 + 0.5) / area.width.max(1) as f64 * span
+    }
+
+    // Drag to zoom into a range, scroll to zoom around the pointer, click to
+    // move the active marker and right click to add one
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        // the band power charts have no frequency axis
+        if matches!(self.visualization_mode, VisualizationMode::Power) {
+            return;
+        }
+        let area = self.chart_area.get();
+        let inside = area.contains((mouse.column, mouse.row).into());
+
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) if inside => {
+                self.selection = Some((mouse.column, mouse.column));
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some((_, end)) = &mut self.selection {
+                    *end = mouse.column.clamp(area.x, area.right().saturating_sub(1));
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                if let Some((start, end)) = self.selection.take() {
+                    // a click without a drag does not zoom
+                    if start.abs_diff(end) >= 2 {
+                        let (from, to) = (self.column_mhz(start), self.column_mhz(end));
+                        let tuning = self.zoom.select(Tuning::of(&self.params), from, to);
+                        self.retune(tuning);
+                    } else {
+                        let hz = self.column_hz(start);
+                        match self.markers.active_mut() {
+                            Some(marker) => {
+                                marker.hz = hz;
+                                marker.tracking = false;
+                            }
+                            None => {
+                                self.markers.add(hz);
+                            }
+                        }
+                    }
+                }
+            }
+            MouseEventKind::Down(MouseButton::Right) if inside => {
+                self.markers.add(self.column_hz(mouse.column));
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown if inside => {
+                let factor = if mouse.kind == MouseEventKind::ScrollUp {
+                    ZOOM_STEP
+                } else {
+                    1.0 / ZOOM_STEP
+                };
+                let center = self.column_mhz(mouse.column);
+                let tuning = self.zoom.zoom(Tuning::of(&self.params), factor, center);
+                self.retune(tuning);
+            }
+            _ => {}
+        }
+    }
+
+    /// Colour and level range in dB, automatic or from the command line
+    /// Traces as drawn, those of the pause while it lasts
+    fn shown_traces(&self) -> &Traces {
+        self.frozen.as_ref().map_or(&self.traces, |f| &f.traces)
+    }
+
+    /// Markers as drawn, tracking ones on the peak of the shown sweep while paused
+    fn shown_markers(&self) -> Cow<'_, Markers> {
+        match (&self.frozen, &self.current_sweep) {
+            (Some(_), Some(sweep)) => {
+                let mut markers = self.markers.clone();
+                markers.track(sweep);
+                Cow::Owned(markers)
+            }
+            _ => Cow::Borrowed(&self.markers),
+        }
+    }
+
+    fn db_range(&self) -> (f32, f32) {
+        let auto_range = match &self.frozen {
+            Some(frozen) => frozen.auto_range.filter(|_| self.autoscale.is_some()),
+            None => self.autoscale.as_ref().and_then(|a| a.range()),
+        };
+        auto_range.unwrap_or((self.params.min_db, self.params.max_db))
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let area = frame.area();
+
+        let band_strip_height = if self.band_plans.is_empty() { 0 } else { 1 };
+
+        // Create a vertical layout
+        let chunks = Layout::vertical([
+            Constraint::Length(7),                 // Title and info
+            Constraint::Length(band_strip_height), // Band plan strip
+            Constraint::Fill(1),                   // Chart
+        ])
+        .split(area);
+
+        let info_text = if let Some(sweep) = &self.current_sweep {
+            format!(
+                "{} - {} MHz{}, {} points, min: {:.1} dB, max: {:.1} dB, Bin width {} Hz",
+                self.params.min_freq,
+                self.params.max_freq,
+                match self.zoom.depth() {
+                    0 => String::new(),
+                    depth => format!(" (zoom {})", depth),
+                },
+                sweep.db.len(),
+                sweep.db.iter().cloned().fold(f32::INFINITY, f32::min),
+                sweep.db.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+                sweep.hz_bin_width
+            )
+        } else {
+            "Waiting for spectrum data...".to_string()
+        };
+
+        let radio_params_text = format!(
+            "BB {} dB, IF {} dB, RF AMP {}, ANT {}",
+            self.params.gain,
+            self.params.lna_gain,
+            if self.params.amp_enable == 1 {
+                "ON"
+            } else {
+                "OFF"
+            },
+            if self.params.antenna_enable == 1 {
+                "ON"
+            } else {
+                "OFF"
+            },
+        );
+
+        // Determine mode text
+        let mode_text = match self.visualization_mode {
+            VisualizationMode::Spectrum => "Mode: Spectrum",
+            VisualizationMode::Waterfall => "Mode: Waterfall",
+            VisualizationMode::Split => "Mode: Split",
+            VisualizationMode::Power => "Mode: Band power",
+            VisualizationMode::Channels => "Mode: Channels",
+        };
+
+        // One line inside the title block, clipped on small terminals
+        let header_row = |row: u16| {
+            Rect::new(
+                chunks[0].x + 2,
+                chunks[0].y + row,
+                chunks[0].width.saturating_sub(4),
+                1,
+            )
+            .intersection(chunks[0])
+        };
+
+        let mut title_block = Block::bordered().title(
+            Line::from("HackRF Spectrum Analyzer")
+                .blue()
+                .bold()
+                .centered(),
+        );
+        if let Some(status) = &self.status {
+            title_block =
+                title_block.title(Line::from(format!(" {} ", status)).gray().right_aligned());
+        }
+
+        frame.render_widget(title_block, chunks[0]);
+
+        if !self.data_points.is_empty() {
+            let title = Line::from(vec![
+                Span::styled(
+                    info_text,
+                    Style::default()
+                        .fg(Color::Cyan)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(" [q] Quit", Style::default().fg(Color::Gray)),
+                Span::styled(" [r] Reset", Style::default().fg(Color::Gray)),
+                Span::styled(" [m] Mode", Style::default().fg(Color::Gray)),
+                Span::styled(" [d] Downscale", Style::default().fg(Color::Gray)),
+                Span::styled(" [c/g/k] Colours", Style::default().fg(Color::Gray)),
+                Span::styled(" [a] Auto dB", Style::default().fg(Color::Gray)),
+                Span::styled(" [+/-] Zoom, whole MHz", Style::default().fg(Color::Gray)),
+            ]);
+
+            // Render info text on first line
+            frame.render_widget(ratatui::widgets::Paragraph::new(title), header_row(1));
+
+            // Render radio parameters and mode on second line
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(Line::from(vec![
+                    Span::styled(radio_params_text, Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        format!(
+                            " - {} - Downscale: {} - Range: {:.0} to {:.0} dB{} - Colours: {} ({}) gamma {:.1} contrast {:.1}",
+                            mode_text,
+                            match self.downscale_mode {
+                                DownscaleMode::Peak => "Peak",
+                                DownscaleMode::Average => "Average",
+                            },
+                            self.db_range().0,
+                            self.db_range().1,
+                            if self.autoscale.is_some() { " (auto)" } else { "" },
+                            self.colormap.name(),
+                            self.graphics
+                                .as_ref()
+                                .map_or(self.color_depth.name(), |g| g.protocol().name()),
+                            self.colormap.gamma,
+                            self.colormap.contrast,
+                        ),
+                        Style::default().fg(Color::Green),
+                    ),
+                ])),
+                header_row(2),
+            );
+
+            // Render detected peaks on third line
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(self.peaks_line()),
+                header_row(3),
+            );
+
+            // Render markers on fourth line
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(self.markers_line()),
+                header_row(4),
+            );
+
+            // Render sweep rate and latency on fifth line
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(self.stats_line()),
+                header_row(5),
+            );
+
+            self.render_band_strip(frame, chunks[1]);
+
+            // Render visualization based on current mode
+            // markers and the mouse use the frequency axis of the chart area
+            let mut chart_area = chunks[2];
+            let spectrum_area = match self.visualization_mode {
+                VisualizationMode::Spectrum => {
+                    self.render_spectrum_chart(frame, chunks[2]);
+                    chunks[2]
+                }
+                VisualizationMode::Waterfall => {
+                    self.render_waterfall(frame, chunks[2]);
+                    Rect::default()
+                }
+                VisualizationMode::Channels => {
+                    let [channels, waterfall] = Layout::vertical([
+                        Constraint::Percentage(self.split_ratio),
+                        Constraint::Fill(1),
+                    ])
+                    .areas(chunks[2]);
+                    self.render_channel_bars(frame, channels);
+                    self.render_waterfall(frame, waterfall);
+                    chart_area = waterfall;
+                    Rect::default()
+                }
+                VisualizationMode::Split => {
+                    // both share the frequency axis of the full width
+                    let [spectrum, waterfall] = Layout::vertical([
+                        Constraint::Percentage(self.split_ratio),
+                        Constraint::Fill(1),
+                    ])
+                    .areas(chunks[2]);
+                    self.render_spectrum_chart(frame, spectrum);
+                    self.render_waterfall(frame, waterfall);
+                    spectrum
+                }
+                VisualizationMode::Power => {
+                    self.render_band_power(frame, chunks[2]);
+                    Rect::default()
+                }
+            };
+            // the trace chart keeps its axis labels out of the plot
+            let mut spectrum_area = self.plot_area(Block::bordered().inner(spectrum_area));
+            if self.axis_gutter() > 0 {
+                spectrum_area.height = spectrum_area.height.saturating_sub(2);
+            }
+            self.spectrum_area.set(spectrum_area);
+
+            self.chart_area
+                .set(self.plot_area(Block::bordered().inner(chart_area)));
+            if !matches!(self.visualization_mode, VisualizationMode::Power) {
+                self.render_markers(frame);
+                self.render_selection(frame);
+            }
+            self.render_settings(frame);
+        } else {
+            // Render info text on first line
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(Line::from(Span::styled(
+                    info_text,
+                    Style::default().fg(Color::Gray),
+                ))),
+                header_row(1),
+            );
+
+            // Render radio parameters on second line
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(Line::from(Span::styled(
+                    radio_params_text,
+                    Style::default().fg(Color::Yellow),
+                ))),
+                header_row(2),
+            );
+        }
+    }
+
+    fn peaks_line(&self) -> Line<'_> {
+        if self.peaks.is_empty() {
+            return Line::from(Span::styled(
+                format!("Peaks: none above {} dB", self.params.peak_threshold),
+                Style::default().fg(Color::Gray),
+            ));
+        }
+
+        let mut spans = vec![Span::styled("Peaks:", Style::default().fg(Color::Magenta))];
+        for peak in &self.peaks {
+            spans.push(Span::styled(
+                format!(" {:.3} MHz {:.1} dB", peak.hz as f64 / 1_000_000.0, peak.db),
+                Style::default().fg(Color::White),
+            ));
+            spans.push(Span::styled(
+                self.allocations.annotate(peak.hz),
+                Style::default().fg(Color::Gray),
+            ));
+        }
+        Line::from(spans)
+    }
+
+    fn stats_line(&self) -> Line<'_> {
+        let rate = self
+            .stats
+            .sweeps_per_second()
+            .map_or("-- sweeps/s".to_string(), |r| format!("{:.2} sweeps/s", r));
+        let latency = self
+            .stats
+            .latency()
+            .map_or("--".to_string(), |l| format!("{} ms", l.as_millis()));
+        Line::from(vec![
+            Span::styled(
+                format!("{}, latency {}", rate, latency),
+                Style::default().fg(Color::Cyan),
+            ),
+            Span::styled(
+                format!(
+                    ", {} dropped, history {}/{}",
+                    self.stats.dropped,
+                    self.waterfall_history.len(),
+                    self.max_waterfall_lines
+                ),
+                Style::default().fg(Color::Gray),
+            ),
+        ])
+    }
+
+    fn markers_line(&self) -> Line<'_> {
+        let markers = self.shown_markers();
+        if markers.is_empty() {
+            return Line::from(Span::styled(
+                "Markers: none, [n] adds one",
+                Style::default().fg(Color::Gray),
+            ));
+        }
+
+        let level_at = |hz| self.current_sweep.as_ref().and_then(|s| level(s, hz));
+        let reference = markers.reference().map(|m| (m.hz, level_at(m.hz)));
+
+        let mut spans = vec![Span::styled(
+            "Markers:",
+            Style::default().fg(Color::Magenta),
+        )];
+        for (i, marker) in markers.iter().enumerate() {
+            let db = level_at(marker.hz);
+            let text = match reference {
+                Some((ref_hz, ref_db)) if !markers.is_reference(i) => format!(
+                    "{}\u{394} {:+.3} MHz {}",
+                    i + 1,
+                    (marker.hz as f64 - ref_hz as f64) / 1_000_000.0,
+                    match (db, ref_db) {
+                        (Some(db), Some(ref_db)) => format!("{:+.1} dB", db - ref_db),
+                        _ => "-- dB".to_string(),
+                    }
+                ),
+                _ => format!(
+                    "{}{} {:.3} MHz {}",
+                    i + 1,
+                    if markers.is_reference(i) { "R" } else { "" },
+                    marker.hz as f64 / 1_000_000.0,
+                    db.map_or("-- dB".to_string(), |db| format!("{:.1} dB", db))
+                ),
+            };
+
+            let mut style = Style::default().fg(MARKER_COLORS[i]);
+            if markers.active() == Some(i) {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(text, style));
+            if marker.tracking {
+                spans.push(Span::styled(" track", Style::default().fg(Color::Gray)));
+            }
+        }
+        Line::from(spans)
+    }
+
+    // Marker numbers along the top of the chart, and on the spectrum trace
+    fn render_markers(&self, frame: &mut Frame) {
+        let markers = self.shown_markers();
+        let area = self.chart_area.get();
+        if area.height == 0 {
+            return;
+        }
+        // on the difference trace while it is drawn
+        let reference = self
+            .current_sweep
+            .as_ref()
+            .filter(|_| self.traced() && self.show_difference)
+            .and_then(|s| self.shown_traces().aligned_reference(s));
+        let (min_db, max_db) = match reference {
+            Some(_) => self.difference_range(),
+            None => self.db_range(),
+        };
+        let level_at = |hz| {
+            let db = self.current_sweep.as_ref().and_then(|s| level(s, hz))?;
+            match &reference {
+                Some(reference) => level(reference, hz).map(|reference| db - reference),
+                None => Some(db),
+            }
+        };
+
+        for (i, marker) in markers.iter().enumerate() {
+            let Some(x) = self.hz_column(marker.hz) else {
+                continue;
+            };
+            let style = Style::default()
+                .fg(Color::Black)
+                .bg(MARKER_COLORS[i])
+                .add_modifier(Modifier::BOLD);
+            let label = char::from_digit(i as u32 + 1, 10).unwrap_or('?');
+            frame.buffer_mut()[(x, area.y)]
+                .set_char(label)
+                .set_style(style);
+
+            let spectrum = self.spectrum_area.get();
+            if let (true, Some(db)) = (spectrum.height > 0, level_at(marker.hz)) {
+                let normalized = ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0);
+                let row = ((1.0 - normalized) * (spectrum.height - 1) as f32).round() as u16;
+                frame.buffer_mut()[(x, spectrum.y + row)]
+                    .set_char('\u{25c6}')
+                    .set_fg(MARKER_COLORS[i]);
+            }
+        }
+    }
+
+    // Settings list in a box over the chart
+    fn render_settings(&self, frame: &mut Frame) {
+        let Some(panel) = &self.settings else {
+            return;
+        };
+        let area = self.chart_area.get();
+        let width = 36.min(area.width);
+        let height = (SETTINGS.len() as u16 + 4).min(area.height);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+
+        let mut lines: Vec<Line> = SETTINGS
+            .iter()
+            .enumerate()
+            .map(|(i, setting)| {
+                let style = if i == panel.selected {
+                    Style::default().fg(Color::Black).bg(Color::Yellow)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                Line::from(Span::styled(
+                    format!(
+                        " {:<15}{:>17} ",
+                        setting.label(),
+                        setting.value(&self.params)
+                    ),
+                    style,
+                ))
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            " \u{2191}\u{2193} select  \u{2190}\u{2192} change  s close",
+            Style::default().fg(Color::Gray),
+        )));
+
+        frame.render_widget(ratatui::widgets::Clear, popup);
+        frame.render_widget(
+            ratatui::widgets::Paragraph::new(lines).block(Block::bordered().title("Settings")),
+            popup,
+        );
+    }
+
+    fn render_band_strip(&self, frame: &mut Frame, area: Rect) {
+        if self.band_plans.is_empty() || area.height == 0 || area.width < 2 {
+            return;
+        }
+
+        // Line up with the inside of the chart border below
+        let area = Rect::new(area.x + 1, area.y, area.width - 2, 1);
+        let width = area.width as usize;
+
+        let (hz_low, hz_high) = self.shown_span();
+        let bands: Vec<_> = self
+            .band_plans
+            .iter()
+            .flat_map(|plan| plan.bands_in(hz_low, hz_high))
+            .collect();
+
+        // Band covering the center of each column, later plans drawn on top
+        let hz_per_col = (hz_high - hz_low) as f64 / width as f64;
+        let columns: Vec<Option<usize>> = (0..width)
+            .map(|x| {
+                let hz = hz_low as f64 + (x as f64 + 0.5) * hz_per_col;
+                bands
+                    .iter()
+                    .rposition(|b| (b.start_hz as f64) <= hz && hz < b.stop_hz as f64)
+            })
+            .collect();
+
+        // Merge runs of columns showing the same band into a single labelled span
+        let mut spans = Vec::new();
+        let mut x = 0;
+        while x < width {
+            let current = columns[x];
+            let run = columns[x..].iter().take_while(|&&c| c == current).count();
+
+            let span = match current {
+                Some(i) => {
+                    let band = bands[i];
+                    let bg = self.color_depth.color(band.color);
+                    let label: String = band.name.chars().take(run).collect();
+                    Span::styled(
+                        format!("{:^run$}", label),
+                        Style::default().fg(Color::White).bg(bg),
+                    )
+                }
+                None => Span::raw(" ".repeat(run)),
+            };
+            spans.push(span);
+            x += run;
+        }
+
+        frame.render_widget(ratatui::widgets::Paragraph::new(Line::from(spans)), area);
+    }
+
+    // Line traces on axes, with a legend and the threshold lines
+    fn render_trace_chart(&self, frame: &mut Frame, area: Rect) {
+        let Some(current) = &self.current_sweep else {
+            return;
+        };
+        let (hz_low, hz_high) = self.shown_span();
+        let min_freq = hz_low as f64 / 1_000_000.0;
+        let max_freq = hz_high as f64 / 1_000_000.0;
+        let differenced = self.differenced(current);
+        let (min_db, max_db) = if differenced {
+            self.difference_range()
+        } else {
+            self.db_range()
+        };
+
+        // dB labels and axis on the left, axis and frequency labels below,
+        // unless the area is too small for them
+        let db_ticks = self.db_ticks(area.height.saturating_sub(2), (min_db, max_db));
+        let labels_width = db_ticks.iter().map(|(_, l)| l.len()).max().unwrap_or(0) as u16;
+        let axes = area.height >= 6 && area.width >= 4 * (labels_width + 1);
+        let gutter = if axes { labels_width + 1 } else { 0 };
+        self.trace_gutter.set(gutter);
+        let plot = if axes {
+            Rect::new(
+                area.x + gutter,
+                area.y,
+                area.width - gutter,
+                area.height - 2,
+            )
+        } else {
+            area
+        };
+
+        // two braille dots per column
+        let points = (plot.width as usize * 2).max(2);
+        let to_points = |sweep: &Sweep, levels: Vec<f32>| -> Vec<(f64, f64)> {
+            let span = (sweep.hz_high - sweep.hz_low) as f64;
+            levels
+                .iter()
+                .enumerate()
+                .map(|(i, &db)| {
+                    let hz = sweep.hz_low as f64 + span * (i as f64 + 0.5) / points as f64;
+                    (hz / 1_000_000.0, db as f64)
+                })
+                .collect()
+        };
+        let trace = |sweep: &Sweep| to_points(sweep, sweep.resample(points, self.downscale_mode));
+        let difference = |sweep: &Sweep| {
+            self.resampled_difference(sweep, points)
+                .map(|levels| to_points(sweep, levels))
+        };
+        let level = |db: f32| vec![(min_freq, db as f64), (max_freq, db as f64)];
+
+        let mut lines = Vec::new();
+        if differenced {
+            // every trace less the reference, which is the zero line
+            let live = difference(current).unwrap_or_default();
+            let above: Vec<(f64, f64)> = live
+                .iter()
+                .copied()
+                .filter(|&(_, db)| db >= self.reference_margin as f64)
+                .collect();
+            lines.push((
+                format!("{} - ref", TraceKind::Live.name()),
+                TraceKind::Live.color(),
+                live,
+                GraphType::Line,
+            ));
+            for (kind, sweep) in self.shown_traces().iter() {
+                let (name, points) = match kind {
+                    TraceKind::Reference => (kind.name().to_string(), Some(level(0.0))),
+                    _ => (format!("{} - ref", kind.name()), difference(sweep)),
+                };
+                if let Some(points) = points {
+                    lines.push((name, kind.color(), points, GraphType::Line));
+                }
+            }
+            lines.push((
+                format!("+{:.0} dB", self.reference_margin),
+                Color::Red,
+                level(self.reference_margin),
+                GraphType::Line,
+            ));
+            if !above.is_empty() {
+                lines.push((
+                    "Above".to_string(),
+                    Color::LightRed,
+                    above,
+                    GraphType::Scatter,
+                ));
+            }
+        } else {
+            lines.push((
+                TraceKind::Live.name().to_string(),
+                TraceKind::Live.color(),
+                trace(current),
+                GraphType::Line,
+            ));
+            for (kind, sweep) in self.shown_traces().iter() {
+                lines.push((
+                    kind.name().to_string(),
+                    kind.color(),
+                    trace(sweep),
+                    GraphType::Line,
+                ));
+            }
+            let peak_level = noise_floor(&current.db) + self.params.peak_threshold;
+            lines.push((
+                format!("Peaks {:.0} dB", peak_level),
+                Color::DarkGray,
+                level(peak_level),
+                GraphType::Line,
+            ));
+            for &threshold in &self.thresholds {
+                lines.push((
+                    format!("{:.0} dB", threshold),
+                    Color::Green,
+                    level(threshold),
+                    GraphType::Line,
+                ));
+            }
+        }
+
+        let datasets = lines
+            .iter()
+            .map(|(name, color, points, graph_type)| {
+                Dataset::default()
+                    .name(name.as_str())
+                    .marker(symbols::Marker::Braille)
+                    .graph_type(*graph_type)
+                    .style(Style::default().fg(*color))
+                    .data(points)
+            })
+            .collect();
+
+        let chart = Chart::new(datasets)
+            .x_axis(Axis::default().bounds([min_freq, max_freq]))
+            .y_axis(Axis::default().bounds([min_db as f64, max_db as f64]))
+            .legend_position(Some(LegendPosition::TopRight))
+            .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)));
+        frame.render_widget(chart, plot);
+        if !axes {
+            return;
+        }
+
+        // Axis lines with a tick at each label
+        let gray = Style::default().fg(Color::Gray);
+        let (axis_x, axis_y) = (plot.x - 1, plot.bottom());
+        let buf = frame.buffer_mut();
+        for y in plot.top()..plot.bottom() {
+            buf[(axis_x, y)].set_char('\u{2502}').set_style(gray);
+        }
+        for x in plot.left()..plot.right() {
+            buf[(x, axis_y)].set_char('\u{2500}').set_style(gray);
+        }
+        buf[(axis_x, axis_y)].set_char('\u{2514}').set_style(gray);
+        for (row, label) in &db_ticks {
+            let y = plot.y + row;
+            buf[(axis_x, y)].set_char('\u{2524}').set_style(gray);
+            buf.set_string(axis_x - label.len() as u16, y, label, gray);
+        }
+        for (column, _) in self.freq_ticks(plot.width) {
+            buf[(plot.x + column, axis_y)]
+                .set_char('\u{252c}')
+                .set_style(gray);
+        }
+        self.render_freq_labels(frame, Rect::new(plot.x, axis_y + 1, plot.width, 1), gray);
+        frame
+            .buffer_mut()
+            .set_stringn(area.x, axis_y + 1, "MHz", gutter as usize - 1, gray);
+    }
+
+    /// Whether the spectrum is shown as line traces
+    fn traced(&self) -> bool {
+        self.show_traces
+            && !self.show_persistence
+            && matches!(
+                self.visualization_mode,
+                VisualizationMode::Spectrum | VisualizationMode::Split
+            )
+    }
+
+    /// Columns taken by the dB axis of the trace chart, left of the frequency axis
+    fn axis_gutter(&self) -> u16 {
+        if self.traced() {
+            self.trace_gutter.get()
+        } else {
+            0
+        }
+    }
+
+    /// Whether the sweep is shown less the reference
+    fn differenced(&self, sweep: &Sweep) -> bool {
+        self.show_difference && self.shown_traces().compares(sweep)
+    }
+
+    /// The sweep less the reference across `width` columns, while the
+    /// difference is shown. Both are downscaled first so that in peak mode
+    /// the noise of many bins does not add up to a rise.
+    fn resampled_difference(&self, sweep: &Sweep, width: usize) -> Option<Vec<f32>> {
+        if !self.show_difference {
+            return None;
+        }
+        let reference = self.shown_traces().aligned_reference(sweep)?;
+        Some(
+            sweep
+                .resample(width, self.downscale_mode)
+                .iter()
+                .zip(reference.resample(width, self.downscale_mode))
+                .map(|(db, reference)| db - reference)
+                .collect(),
+        )
+    }
+
+    /// Level range of the difference display: twice the margin below the
+    /// reference to four times above it
+    fn difference_range(&self) -> (f32, f32) {
+        (-2.0 * self.reference_margin, 4.0 * self.reference_margin)
+    }
+
+    /// Colour levels between 0 and 1 of a sweep across `width` columns. A
+    /// difference to the reference takes the lower half of the colour map
+    /// up to the margin and jumps to the top quarter above it.
+    fn waterfall_levels(&self, sweep: &Sweep, width: usize) -> Vec<f32> {
+        if let Some(difference) = self.resampled_difference(sweep, width) {
+            let (low, high) = self.difference_range();
+            let margin = self.reference_margin;
+            return difference
+                .iter()
+                .map(|&db| {
+                    if db < margin {
+                        0.5 * ((db - low) / (margin - low)).clamp(0.0, 1.0)
+                    } else {
+                        0.75 + 0.25 * ((db - margin) / (high - margin)).min(1.0)
+                    }
+                })
+                .collect();
+        }
+
+        let (min_db, max_db) = self.db_range();
+        sweep
+            .resample(width, self.downscale_mode)
+            .iter()
+            .map(|&db| ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0))
+            .collect()
+    }
+
+    // Part of the area on the shared frequency axis
+    fn plot_area(&self, area: Rect) -> Rect {
+        let gutter = self.axis_gutter().min(area.width);
+        Rect {
+            x: area.x + gutter,
+            width: area.width - gutter,
+            ..area
+        }
+    }
+
+    fn render_spectrum_cells(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width as usize;
+        let height = area.height as usize;
+
+        // Intensity of each pixel: the hits of many sweeps in persistence
+        // mode, otherwise how many points of the last sweep fall into it
+        let grid = if self.show_persistence {
+            self.persistence
+                .intensities(width, height)
+                .unwrap_or_default()
+        } else {
+            let mut density_grid = vec![vec![0u32; height]; width];
+            let mut max_density = 0u32;
+            for (x_pos, y_pos) in self.spectrum_cells(width, height) {
+                density_grid[x_pos][y_pos] += 1;
+                max_density = max_density.max(density_grid[x_pos][y_pos]);
+            }
+            density_grid
+                .iter()
+                .map(|column| {
+                    column
+                        .iter()
+                        .map(|&density| density as f32 / max_density as f32)
+                        .collect()
+                })
+                .collect()
+        };
+
+        // Create a canvas to draw the density-colored spectrum
+        let spectrum = Canvas::default()
+            .marker(symbols::Marker::HalfBlock)
+            .paint(|ctx| {
+                // Now draw each pixel with color based on its density
+                for (x, column) in grid.iter().enumerate() {
+                    for (y, &normalized_density) in column.iter().enumerate() {
+                        // Only draw pixels that have at least one point
+                        if normalized_density > 0.0 {
+                            // Get color based on density
+                            let color = self.get_color_from_value(normalized_density);
+
+                            // Draw a single pixel
+                            ctx.draw(&Rectangle {
+                                x: x as f64,
+                                y: y as f64,
+                                width: 1.0,
+                                height: 1.0,
+                                color,
+                            });
+                        }
+                    }
+                }
+            })
+            .x_bounds([0.0, area.width as f64])
+            .y_bounds([0.0, area.height as f64]);
+
+        frame.render_widget(spectrum, area);
+    }
+
+    // Area under the trace shaded by height, the trace itself at full level
+    fn render_spectrum_image(&self, graphics: &Graphics, area: Rect) {
+        let (hz_low, hz_high) = self.shown_span();
+        let (min_freq, max_freq) = (hz_low as f64 / 1_000_000.0, hz_high as f64 / 1_000_000.0);
+        let (min_db, max_db) = self.db_range();
+        let (min_db, max_db) = (min_db as f64, max_db as f64);
+
+        let mut pixels = graphics.pixels(area);
+        let (width, height) = (pixels.width() as usize, pixels.height());
+
+        // Strongest point in each pixel column
+        let mut columns = vec![f64::NEG_INFINITY; width];
+        for &(freq, db) in &self.data_points {
+            let x = ((freq - min_freq) / (max_freq - min_freq) * (width as f64 - 1.0)) as usize;
+            if let Some(column) = columns.get_mut(x) {
+                *column = column.max(db);
+            }
+        }
+        // zoomed in, bins span several columns
+        for x in 1..width {
+            if columns[x] == f64::NEG_INFINITY {
+                columns[x] = columns[x - 1];
+            }
+        }
+
+        for (x, &db) in columns.iter().enumerate() {
+            if !db.is_finite() {
+                continue;
+            }
+            let normalized = ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0);
+            let top = (normalized * height as f64) as u32;
+            for y in 0..top {
+                pixels.set(x as u32, y, 0.6 * y as f32 / height as f32);
+            }
+            if top > 0 {
+                pixels.set(x as u32, top - 1, 1.0);
+            }
+        }
+        graphics.place(area, pixels, &self.colormap);
+    }
+
+    fn render_spectrum_chart(&self, frame: &mut Frame, area: Rect) {
+        if self.data_points.is_empty() {
+            return;
+        }
+
+        // Create a block with border and title
+        let title = if self.show_persistence {
+            format!("Spectrum - persistence {:.3}", self.persistence.decay())
+        } else if self.show_traces {
+            let mut title = "Spectrum - traces".to_string();
+            if self.shown_traces().max_hold() {
+                title.push_str(", max hold");
+            }
+            if self.shown_traces().average() {
+                title.push_str(", average");
+            }
+            if self.show_difference && self.shown_traces().reference().is_some() {
+                title.push_str(", difference to reference");
+            }
+            title
+        } else {
+            "Spectrum".to_string()
+        };
+        let block = Block::bordered()
+            .title(title)
+            .title_alignment(ratatui::layout::Alignment::Center);
+
+        let inner_area = block.inner(area);
+
+        frame.render_widget(block, area);
+
+        let width = inner_area.width as usize;
+        let height = inner_area.height as usize;
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        if self.show_traces && !self.show_persistence {
+            self.render_trace_chart(frame, inner_area);
+            return;
+        }
+
+        // The persistence grid stays in cells
+        if let Some(graphics) = self.graphics.as_ref().filter(|_| !self.show_persistence) {
+            self.render_spectrum_image(graphics, inner_area);
+        } else {
+            self.render_spectrum_cells(frame, inner_area);
+        }
+
+        // Frequency labels along the bottom, dB labels on the left above them
+        let label_style = Style::default().fg(Color::Gray);
+        self.render_freq_labels(
+            frame,
+            inner_area.rows().next_back().unwrap_or_default(),
+            label_style,
+        );
+        let buf = frame.buffer_mut();
+        for (row, label) in self.db_ticks(inner_area.height, self.db_range()) {
+            if row + 1 < inner_area.height {
+                buf.set_stringn(
+                    inner_area.x,
+                    inner_area.y + row,
+                    format!("{} dB", label),
+                    inner_area.width as usize,
+                    label_style,
+                );
+            }
+        }
+    }
+
+    // One chart of the power over time per window, its statistics in the title
+    fn render_band_power(&self, frame: &mut Frame, area: Rect) {
+        let (Some(newest), false) = (self.band_power.newest(), self.band_power.is_empty()) else {
+            let hint = if self.band_power.is_empty() {
+                "No windows: place two markers and press [b], or start with --band-power MIN-MAX"
+            } else {
+                "Waiting for a sweep covering the windows..."
+            };
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(Line::from(hint).gray()).block(
+                    Block::bordered()
+                        .title("Band power")
+                        .title_alignment(ratatui::layout::Alignment::Center),
+                ),
+                area,
+            );
+            return;
+        };
+
+        let areas =
+            Layout::vertical(self.band_power.iter().map(|_| Constraint::Fill(1))).split(area);
+        for (window, &area) in self.band_power.iter().zip(areas.iter()) {
+            self.render_power_chart(frame, area, window, newest);
+        }
+    }
+
+    // Power of a window over the seconds before the newest sample, on nice dB
+    // and time steps, with the mean as a line
+    fn render_power_chart(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        window: &PowerWindow,
+        newest: DateTime<Utc>,
+    ) {
+        let stats = window.stats();
+        let mut title = vec![Span::styled(
+            format!(" {} ", window.label()),
+            Style::default().fg(Color::Yellow),
+        )];
+        if let Some(stats) = stats {
+            title.push(Span::styled(
+                format!(
+                    "now {:.1} dB, min {:.1}, max {:.1}, mean {:.1} dB ",
+                    stats.now, stats.min, stats.max, stats.mean
+                ),
+                Style::default().fg(Color::Gray),
+            ));
+        }
+        let block = Block::bordered()
+            .title(Line::from(title))
+            .title_alignment(ratatui::layout::Alignment::Center);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let Some(stats) = stats else {
+            return;
+        };
+
+        let points: Vec<(f64, f64)> = window
+            .samples()
+            .map(|&(t, db)| ((t - newest).num_milliseconds() as f64 / 1000.0, db as f64))
+            .collect();
+
+        // dB range widened to whole steps around the levels seen
+        let max_ticks = (inner.height / DB_LABEL_ROWS).max(2) as usize;
+        let (low, high, step) = nice_bounds(stats.min as f64, stats.max as f64, max_ticks);
+        let decimals = decimals_for_step(step);
+        let db_labels: Vec<String> = (0..=((high - low) / step).round() as usize)
+            .map(|i| format!("{:.*}", decimals, low + i as f64 * step))
+            .collect();
+
+        // seconds back to the oldest sample, in whole time steps
+        let seconds = points.first().map_or(0.0, |&(x, _)| -x).max(1.0);
+        let max_labels = (inner.width / TIME_LABEL_COLUMNS).max(2) as f64;
+        let time_step = nice_time_step(seconds / (max_labels - 1.0));
+        let steps = (seconds / time_step as f64).ceil().max(1.0) as i64;
+        let time_labels: Vec<String> = (0..=steps)
+            .rev()
+            .map(|i| match i * time_step {
+                0 => "now".to_string(),
+                s if s % 60 == 0 => format!("-{}m", s / 60),
+                s => format!("-{}s", s),
+            })
+            .collect();
+        let start = -(steps * time_step) as f64;
+
+        let mean = [(start, stats.mean as f64), (0.0, stats.mean as f64)];
+        let datasets = vec![
+            Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::DarkGray))
+                .data(&mean),
+            Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Yellow))
+                .data(&points),
+        ];
+        let gray = Style::default().fg(Color::Gray);
+        let chart = Chart::new(datasets)
+            .x_axis(
+                Axis::default()
+                    .bounds([start, 0.0])
+                    .labels(time_labels)
+                    .style(gray),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([low, high])
+                    .labels(db_labels)
+                    .style(gray),
+            );
+        frame.render_widget(chart, inner);
+    }
+
+    // A bar per channel of its power in the shown sweep, coloured by how often
+    // it was busy recently, with its peak hold as a line; names and occupancy below
+    fn render_channel_bars(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title_alignment(ratatui::layout::Alignment::Center);
+        let hint = |frame: &mut Frame, block: Block, text: String| {
+            frame.render_widget(
+                ratatui::widgets::Paragraph::new(Line::from(text).gray()).block(block),
+                area,
+            );
+        };
+        let bars = match &self.frozen {
+            Some(frozen) => &frozen.bars,
+            None => &self.channel_bars,
+        };
+        let (Some(bars), Some(sweep)) = (bars, &self.current_sweep) else {
+            let text = "No channel plan: start with --channels wifi24, ble or a band plan file";
+            hint(frame, block.title("Channels"), text.to_string());
+            return;
+        };
+
+        let levels: Vec<_> = bars.bars(sweep).collect();
+        let mut title = format!("Channels - {}", bars.name());
+        if let Some((channel, occupancy)) = levels
+            .iter()
+            .filter_map(|bar| bar.occupancy.map(|o| (bar.channel, o)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|&(_, occupancy)| occupancy > 0.0)
+        {
+            title.push_str(&format!(
+                " - busiest {} {:.0}%",
+                channel.name,
+                occupancy * 100.0
+            ));
+        }
+        let block = block.title(title);
+
+        // from a step below the weakest channel to the highest hold
+        let weakest = levels
+            .iter()
+            .filter_map(|bar| bar.power)
+            .fold(f32::INFINITY, f32::min);
+        let highest = levels
+            .iter()
+            .filter_map(|bar| bar.hold.or(bar.power))
+            .fold(f32::NEG_INFINITY, f32::max);
+        if !weakest.is_finite() {
+            let text = format!("No channel of {} in the swept range", bars.name());
+            hint(frame, block, text);
+            return;
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        if inner.height < 4 || inner.width == 0 {
+            return;
+        }
+
+        let height = inner.height - 2;
+        let max_ticks = (height / DB_LABEL_ROWS).max(2) as usize;
+        let (low, high, step) = nice_bounds(weakest as f64, highest as f64, max_ticks);
+        let (low, high) = ((low - step) as f32, high as f32);
+        let db_ticks = self.db_ticks(height, (low, high));
+        let labels_width = db_ticks.iter().map(|(_, l)| l.len()).max().unwrap_or(0) as u16;
+        let gutter = if inner.width >= 4 * (labels_width + 1) {
+            labels_width + 1
+        } else {
+            0
+        };
+        let plot = Rect::new(inner.x + gutter, inner.y, inner.width - gutter, height);
+
+        let gray = Style::default().fg(Color::Gray);
+        let buf = frame.buffer_mut();
+        if gutter > 0 {
+            for (row, label) in &db_ticks {
+                buf.set_string(inner.x, plot.y + row, label, gray);
+            }
+        }
+
+        let rows = |db: f32| ((db - low) / (high - low)).clamp(0.0, 1.0) * height as f32;
+        let (mut free_name, mut free_occupancy) = (plot.x, plot.x);
+        for (i, bar) in levels.iter().enumerate() {
+            let left = plot.x + (i * plot.width as usize / levels.len()) as u16;
+            let right = plot.x + ((i + 1) * plot.width as usize / levels.len()) as u16;
+            if right == left {
+                continue;
+            }
+            // a column between neighbouring bars
+            let columns = left..if right - left >= 2 { right - 1 } else { right };
+
+            if let Some(power) = bar.power {
+                let eighths = (rows(power) * 8.0).round() as u16;
+                let occupancy = bar.occupancy.unwrap_or(0.0);
+                let color = self.get_color_from_value(0.35 + 0.65 * occupancy);
+                for row in 0..height {
+                    let fill = eighths.saturating_sub(row * 8).min(8) as usize;
+                    if fill == 0 {
+                        break;
+                    }
+                    let symbol = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'][fill];
+                    for x in columns.clone() {
+                        buf[(x, plot.bottom() - 1 - row)]
+                            .set_char(symbol)
+                            .set_fg(color);
+                    }
+                }
+            }
+            if let Some(hold) = bar.hold {
+                let row = (rows(hold) as u16).min(height - 1);
+                for x in columns.clone() {
+                    buf[(x, plot.bottom() - 1 - row)]
+                        .set_char('━')
+                        .set_fg(TraceKind::MaxHold.color());
+                }
+            }
+
+            // labels centered under the bar, left out where they would touch
+            let center = (columns.start + columns.end) / 2;
+            let mut label = |text: &str, y: u16, free: &mut u16| {
+                let width = text.chars().count() as u16;
+                let x = center
+                    .saturating_sub(width / 2)
+                    .max(plot.x)
+                    .min(plot.right().saturating_sub(width));
+                if x >= *free && width <= plot.width {
+                    buf.set_string(x, y, text, gray);
+                    *free = x + width + 1;
+                }
+            };
+            label(bar.channel.short_name(), plot.bottom(), &mut free_name);
+            if let Some(occupancy) = bar.occupancy {
+                let occupancy = format!("{:.0}%", occupancy * 100.0);
+                label(&occupancy, plot.bottom() + 1, &mut free_occupancy);
+            }
+        }
+    }
+
+    fn render_waterfall(&self, frame: &mut Frame, area: Rect) {
+        if self.waterfall_history.is_empty() {
+            return;
+        }
+
+        let title = match &self.frozen {
+            Some(frozen) => {
+                let sweep = &self.waterfall_history[frozen.cursor];
+                let newest = &self.waterfall_history[0];
+                format!(
+                    "Waterfall - paused - {} ({:.1} s ago)",
+                    sweep.timestamp.format("%Y-%m-%d %H:%M:%S%.3f UTC"),
+                    (newest.timestamp - sweep.timestamp).num_milliseconds() as f64 / 1000.0
+                )
+            }
+            None => "Waterfall".to_string(),
+        };
+        let title = match self.waterfall_history.front() {
+            Some(newest) if self.differenced(newest) => format!(
+                "{} - difference to reference, +{:.0} dB margin",
+                title, self.reference_margin
+            ),
+            _ => title,
+        };
+        let block = Block::bordered()
+            .title(title)
+            .title_alignment(ratatui::layout::Alignment::Center);
+
+        let inner_area = self.plot_area(block.inner(area));
+
+        frame.render_widget(block, area);
+        let per_row = self.sweeps_per_row();
+        self.waterfall_rows
+            .set(inner_area.height as usize * per_row);
+
+        if let Some(graphics) = &self.graphics {
+            self.render_waterfall_image(graphics, inner_area);
+        } else if self.color_depth == ColorDepth::Ascii {
+            self.render_waterfall_ascii(frame, inner_area);
+        } else {
+            self.render_waterfall_blocks(frame, inner_area);
+        }
+
+        self.render_time_gutter(frame, inner_area);
+
+        // Cursor arrows on the borders, at the selected sweep
+        if let Some(frozen) = &self.frozen {
+            let y = (frozen.cursor - frozen.offset) / per_row;
+            if y < inner_area.height as usize {
+                let row = inner_area.y + inner_area.height - 1 - y as u16;
+                let style = Style::default().fg(Color::Yellow);
+                let buf = frame.buffer_mut();
+                buf[(area.x, row)].set_char('\u{25b6}').set_style(style);
+                buf[(area.right() - 1, row)]
+                    .set_char('\u{25c0}')
+                    .set_style(style);
+            }
+        }
+
+        // Frequency labels on the bottom row
+        self.render_freq_labels(
+            frame,
+            inner_area.rows().next_back().unwrap_or_default(),
+            Style::default().fg(Color::Gray).bg(Color::Black),
+        );
+    }
+
+    // Frequency labels at their columns along a row, as many as fit, with the unit at the end
+    fn render_freq_labels(&self, frame: &mut Frame, row: Rect, style: Style) {
+        let buf = frame.buffer_mut();
+        let mut free = row.x;
+        for (column, label) in self.freq_ticks(row.width) {
+            let width = label.len() as u16;
+            let x = row.x + column.saturating_sub(width / 2).min(row.width - width);
+            buf.set_string(x, row.y, &label, style);
+            free = x + width + FREQ_LABEL_GAP;
+        }
+        if free + 3 <= row.right() {
+            buf.set_string(row.right() - 3, row.y, "MHz", style);
+        }
+    }
+
+    /// Nice frequencies for a row of this width: their column and label,
+    /// leaving out labels that would not fit
+    fn freq_ticks(&self, width: u16) -> Vec<(u16, String)> {
+        let (hz_low, hz_high) = self.shown_span();
+        let (low, high) = (hz_low as f64 / 1_000_000.0, hz_high as f64 / 1_000_000.0);
+        if high <= low || width == 0 {
+            return Vec::new();
+        }
+
+        // fewer ticks until the widest label fits between them
+        let mut max_ticks = (width / FREQ_LABEL_GAP).max(1) as usize;
+        let ticks = loop {
+            let decimals = decimals_for_step(nice_step(high - low, max_ticks));
+            let ticks: Vec<(f64, String)> = nice_ticks(low, high, max_ticks)
+                .into_iter()
+                .map(|mhz| (mhz, format!("{:.*}", decimals, mhz)))
+                .collect();
+            let widest = ticks.iter().map(|(_, l)| l.len()).max().unwrap_or(0) as u16;
+            let fit = (width / (widest + FREQ_LABEL_GAP)) as usize;
+            if ticks.len() <= fit || max_ticks == 1 {
+                break ticks;
+            }
+            max_ticks = fit.clamp(1, max_ticks - 1);
+        };
+
+        let mut free = 0;
+        let mut placed = Vec::new();
+        for (mhz, label) in ticks {
+            let column = (((mhz - low) / (high - low) * width as f64) as u16).min(width - 1);
+            let label_width = label.len() as u16;
+            if label_width > width {
+                continue;
+            }
+            let x = column
+                .saturating_sub(label_width / 2)
+                .min(width - label_width);
+            if x < free {
+                continue;
+            }
+            free = x + label_width + FREQ_LABEL_GAP;
+            placed.push((column, label));
+        }
+        placed
+    }
+
+    /// Nice dB levels in the range for a plot of this height: their row from
+    /// the top and label
+    fn db_ticks(&self, height: u16, (min_db, max_db): (f32, f32)) -> Vec<(u16, String)> {
+        let (min_db, max_db) = (min_db as f64, max_db as f64);
+        if max_db <= min_db || height == 0 {
+            return Vec::new();
+        }
+        let max_ticks = (height / DB_LABEL_ROWS).max(2) as usize;
+        let decimals = decimals_for_step(nice_step(max_db - min_db, max_ticks));
+        nice_ticks(min_db, max_db, max_ticks)
+            .into_iter()
+            .map(|db| {
+                let normalized = (db - min_db) / (max_db - min_db);
+                let row = ((1.0 - normalized) * (height - 1) as f64).round() as u16;
+                (row, format!("{:.*}", decimals, db))
+            })
+            .collect()
+    }
+
+    // Columns being dragged over, shown reversed
+    fn render_selection(&self, frame: &mut Frame) {
+        let Some((start, end)) = self.selection else {
+            return;
+        };
+        let area = self.chart_area.get();
+        let columns = Rect::new(start.min(end), area.y, start.abs_diff(end) + 1, area.height)
+            .intersection(area);
+        frame
+            .buffer_mut()
+            .set_style(columns, Style::default().add_modifier(Modifier::REVERSED));
+    }
+
+    // Age or time of every few rows on the left, gaps and settings changes on the right
+    fn render_time_gutter(&self, frame: &mut Frame, area: Rect) {
+        let Some(newest) = self.waterfall_history.front() else {
+            return;
+        };
+        let label_style = Style::default().fg(Color::White).bg(Color::Black);
+        let per_row = self.sweeps_per_row();
+        let buf = frame.buffer_mut();
+
+        for (i, sweep) in self
+            .waterfall_history
+            .iter()
+            .skip(self.view_offset())
+            .take(area.height as usize * per_row)
+            .enumerate()
+        {
+            let y = i / per_row;
+            let row = area.y + area.height - 1 - y as u16;
+
+            // the bottom row holds the frequency labels
+            if i.is_multiple_of(per_row) && y > 0 && y.is_multiple_of(TIME_LABEL_ROWS) {
+                let label = if self.absolute_time {
+                    sweep.timestamp.format("%H:%M:%S").to_string()
+                } else {
+                    let age = (newest.timestamp - sweep.timestamp).num_milliseconds();
+                    format!("-{:.1}s", age as f64 / 1000.0)
+                };
+                buf.set_stringn(area.x, row, &label, area.width as usize, label_style);
+            }
+
+            for event in self.row_events.at(sweep.timestamp).filter(|_| y > 0) {
+                let (text, color) = match event {
+                    RowEvent::Gap(seconds) => (format!(" gap {:.1}s ", seconds), Color::Red),
+                    RowEvent::Changed(change) => (format!(" {} ", change), Color::Cyan),
+                };
+                let width = (text.chars().count() as u16).min(area.width);
+                buf.set_stringn(
+                    area.right() - width,
+                    row,
+                    &text,
+                    width as usize,
+                    Style::default().fg(Color::Black).bg(color),
+                );
+            }
+        }
+    }
+
+    /// Sweeps drawn in each terminal row of the waterfall
+    fn sweeps_per_row(&self) -> usize {
+        if let Some(graphics) = &self.graphics {
+            graphics.cell_size().1 as usize
+        } else if self.color_depth == ColorDepth::Ascii {
+            1
+        } else {
+            2
+        }
+    }
+
+    // One sweep per pixel row, newest at the bottom
+    fn render_waterfall_image(&self, graphics: &Graphics, area: Rect) {
+        let mut pixels = graphics.pixels(area);
+        for (y, sweep) in self
+            .waterfall_history
+            .iter()
+            .skip(self.view_offset())
+            .take(pixels.height() as usize)
+            .enumerate()
+        {
+            for (x, &level) in self
+                .waterfall_levels(sweep, pixels.width() as usize)
+                .iter()
+                .enumerate()
+            {
+                pixels.set(x as u32, y as u32, level);
+            }
+        }
+        graphics.place(area, pixels, &self.colormap);
+    }
+
+    // Two sweeps per cell: the older one in the upper half block, the newer
+    // one in the background showing below it
+    fn render_waterfall_blocks(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width as usize;
+        let colors = |sweep: &Sweep| -> Vec<Color> {
+            self.waterfall_levels(sweep, width)
+                .iter()
+                .map(|&level| self.get_color_from_value(level))
+                .collect()
+        };
+
+        let sweeps: Vec<&Sweep> = self
+            .waterfall_history
+            .iter()
+            .skip(self.view_offset())
+            .take(area.height as usize * 2)
+            .collect();
+        let buf = frame.buffer_mut();
+
+        // newest sweeps at the bottom
+        for (y, pair) in sweeps.chunks(2).enumerate() {
+            let row = area.y + area.height - 1 - y as u16;
+            let lower = colors(pair[0]);
+            match pair.get(1).map(|sweep| colors(sweep)) {
+                Some(upper) => {
+                    for (x, (&fg, &bg)) in upper.iter().zip(&lower).enumerate() {
+                        buf[(area.x + x as u16, row)]
+                            .set_char('\u{2580}')
+                            .set_fg(fg)
+                            .set_bg(bg);
+                    }
+                }
+                // the oldest sweep alone in its row
+                None => {
+                    for (x, &fg) in lower.iter().enumerate() {
+                        buf[(area.x + x as u16, row)]
+                            .set_char('\u{2584}')
+                            .set_fg(fg);
+                    }
+                }
+            }
+        }
+    }
+
+    // Without colours, each cell shows the level as a character of the ramp
+    fn render_waterfall_ascii(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width as usize;
+        let buf = frame.buffer_mut();
+
+        // newest sweep at the bottom, like the canvas
+        for (y, sweep) in self
+            .waterfall_history
+            .iter()
+            .skip(self.view_offset())
+            .take(area.height as usize)
+            .enumerate()
+        {
+            let row = area.y + area.height - 1 - y as u16;
+            for (x, &level) in self.waterfall_levels(sweep, width).iter().enumerate() {
+                buf[(area.x + x as u16, row)].set_char(ascii_shade(level));
+            }
+        }
+    }
+
+    fn get_color_from_value(&self, value: f32) -> Color {
+        // Same colour map as the image output, reduced to what the terminal shows
+        self.color_depth.color(self.colormap.eval(value))
+    }
+}
+
+/// Range around [min, max] widened to whole nice steps, and the step
+fn nice_bounds(min: f64, max: f64, max_ticks: usize) -> (f64, f64, f64) {
+    let step = nice_step((max - min).max(1.0), max_ticks);
+    let low = (min / step).floor() * step;
+    let high = ((max / step).ceil() * step).max(low + step);
+    (low, high, step)
+}
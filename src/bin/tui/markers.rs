@@ -0,0 +1,131 @@
+// Frequency markers: level readout, delta to a reference, peak search and tracking
+
+use hackrf_spectrum_analyzer::{peaks::find_peaks, sweep::Sweep};
+
+/// Maximum number of markers
+pub const MAX_MARKERS: usize = 4;
+
+/// Bins a peak must be from a stronger one to count as the next peak
+const PEAK_SEPARATION_BINS: f32 = 5.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub hz: u64,
+    /// Move to the strongest bin of every new sweep
+    pub tracking: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Markers {
+    list: Vec<Marker>,
+    /// Marker moved by the keys
+    active: usize,
+    /// Marker the others are measured from
+    reference: Option<usize>,
+}
+
+impl Markers {
+    pub fn iter(&self) -> impl Iterator<Item = &Marker> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn active(&self) -> Option<usize> {
+        (!self.list.is_empty()).then_some(self.active)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Marker> {
+        self.list.get_mut(self.active)
+    }
+
+    pub fn reference(&self) -> Option<&Marker> {
+        self.reference.and_then(|i| self.list.get(i))
+    }
+
+    pub fn is_reference(&self, index: usize) -> bool {
+        self.reference == Some(index)
+    }
+
+    /// Add a marker and make it the active one, false when all are in use
+    pub fn add(&mut self, hz: u64) -> bool {
+        if self.list.len() >= MAX_MARKERS {
+            return false;
+        }
+        self.list.push(Marker {
+            hz,
+            tracking: false,
+        });
+        self.active = self.list.len() - 1;
+        true
+    }
+
+    pub fn remove_active(&mut self) {
+        if self.list.is_empty() {
+            return;
+        }
+        self.list.remove(self.active);
+        self.reference = match self.reference {
+            Some(r) if r == self.active => None,
+            Some(r) if r > self.active => Some(r - 1),
+            r => r,
+        };
+        self.active = self.active.min(self.list.len().saturating_sub(1));
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.list.is_empty() {
+            self.active = (self.active + 1) % self.list.len();
+        }
+    }
+
+    /// Make the active marker the reference, or clear it if it already is
+    pub fn toggle_reference(&mut self) {
+        if self.list.is_empty() {
+            return;
+        }
+        self.reference = if self.reference == Some(self.active) {
+            None
+        } else {
+            Some(self.active)
+        };
+    }
+
+    /// Move the tracking markers to the strongest bin of the sweep
+    pub fn track(&mut self, sweep: &Sweep) {
+        let Some(hz) = peak_hz(sweep) else {
+            return;
+        };
+        for marker in self.list.iter_mut().filter(|m| m.tracking) {
+            marker.hz = hz;
+        }
+    }
+}
+
+/// Level of the bin at the given frequency
+pub fn level(sweep: &Sweep, hz: u64) -> Option<f32> {
+    sweep.bin_at(hz).map(|bin| sweep.db[bin])
+}
+
+/// Center frequency of the strongest bin
+pub fn peak_hz(sweep: &Sweep) -> Option<u64> {
+    sweep
+        .db
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(bin, _)| sweep.bin_hz(bin))
+}
+
+/// The strongest peak weaker than the level at `hz`, like the next peak key
+/// of a spectrum analyzer
+pub fn next_peak(sweep: &Sweep, hz: u64) -> Option<u64> {
+    let current = level(sweep, hz)?;
+    let separation = (sweep.hz_bin_width * PEAK_SEPARATION_BINS) as u64;
+    find_peaks(sweep, 0.0, separation, usize::MAX)
+        .into_iter()
+        .find(|p| p.db < current && p.hz != hz)
+        .map(|p| p.hz)
+}
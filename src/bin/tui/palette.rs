@@ -0,0 +1,130 @@
+// Mapping colour map output to what the terminal can display
+
+use std::env;
+
+use clap::ValueEnum;
+use ratatui::style::Color;
+
+/// Characters from empty to full, used when the terminal has no colours
+pub const ASCII_RAMP: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// Levels of the xterm-256 6x6x6 colour cube
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The 16 ANSI colours with their usual xterm RGB values
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Colours the terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorDepth {
+    /// 24-bit RGB
+    Truecolor,
+    /// xterm 256 colour palette
+    #[value(name = "256")]
+    Ansi256,
+    /// 16 ANSI colours
+    #[value(name = "16")]
+    Ansi16,
+    /// No colours, the waterfall is drawn with a character ramp
+    Ascii,
+}
+
+impl ColorDepth {
+    /// Guess the colour depth from the environment
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return Self::Ascii;
+        }
+
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return Self::Truecolor;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" {
+            Self::Ascii
+        } else if term.contains("direct") {
+            Self::Truecolor
+        } else if term.contains("256") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Truecolor => "truecolor",
+            Self::Ansi256 => "256",
+            Self::Ansi16 => "16",
+            Self::Ascii => "ascii",
+        }
+    }
+
+    /// Closest colour the terminal can display
+    pub fn color(self, (r, g, b): (u8, u8, u8)) -> Color {
+        match self {
+            Self::Truecolor => Color::Rgb(r, g, b),
+            Self::Ansi256 => Color::Indexed(xterm_256(r, g, b)),
+            Self::Ansi16 => {
+                ANSI_16
+                    .iter()
+                    .min_by_key(|(_, c)| distance((r, g, b), *c))
+                    .expect("palette is not empty")
+                    .0
+            }
+            Self::Ascii => Color::Reset,
+        }
+    }
+}
+
+/// Character of the ramp for a value in 0.0-1.0
+pub fn ascii_shade(value: f32) -> char {
+    let last = ASCII_RAMP.len() - 1;
+    ASCII_RAMP[(value.clamp(0.0, 1.0) * last as f32).round() as usize]
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+// Nearest entry of the colour cube (16-231) or the grey ramp (232-255)
+fn xterm_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(v))
+            .expect("levels are not empty")
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey_level = 8 + 10 * grey_index;
+    let grey = (grey_level, grey_level, grey_level);
+
+    if distance((r, g, b), grey) < distance((r, g, b), cube) {
+        232 + grey_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
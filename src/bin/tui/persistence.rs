@@ -0,0 +1,97 @@
+// Persistence (phosphor) display: trace hits accumulated over many sweeps
+
+/// Per-sweep decay factors offered, from short to long persistence
+pub const DECAY_LIMITS: (f32, f32) = (0.5, 0.995);
+
+/// Hits of the spectrum cells, fading with every sweep
+#[derive(Debug)]
+pub struct Persistence {
+    width: usize,
+    height: usize,
+    /// Column major, like the density grid of a single sweep
+    hits: Vec<f32>,
+    /// Fraction of the hits left after each sweep
+    decay: f32,
+}
+
+impl Persistence {
+    pub fn new(decay: f32) -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            hits: Vec::new(),
+            decay: decay.clamp(DECAY_LIMITS.0, DECAY_LIMITS.1),
+        }
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    /// Change the decay, moving the remaining time constant by a factor
+    pub fn scale_decay(&mut self, factor: f32) {
+        let sweeps = 1.0 / (1.0 - self.decay) * factor;
+        self.decay = (1.0 - 1.0 / sweeps).clamp(DECAY_LIMITS.0, DECAY_LIMITS.1);
+    }
+
+    pub fn clear(&mut self) {
+        self.hits.clear();
+        self.width = 0;
+        self.height = 0;
+    }
+
+    /// Fade the previous sweeps and add the cells hit by a new one; the grid
+    /// starts over when its size changes
+    pub fn accumulate(
+        &mut self,
+        width: usize,
+        height: usize,
+        cells: impl IntoIterator<Item = (usize, usize)>,
+    ) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.hits = vec![0.0; width * height];
+        }
+
+        for hits in self.hits.iter_mut() {
+            *hits *= self.decay;
+        }
+
+        // a cell counts once per sweep, however many bins land in it
+        let mut hit = vec![false; width * height];
+        for (x, y) in cells {
+            if x < width && y < height {
+                hit[x * height + y] = true;
+            }
+        }
+        for (hits, hit) in self.hits.iter_mut().zip(hit) {
+            if hit {
+                *hits += 1.0;
+            }
+        }
+    }
+
+    /// Intensity of every cell in 0.0-1.0, column major, `None` if the grid
+    /// was made for another size
+    ///
+    /// A cell hit by every sweep is at 1.0; the scale is logarithmic so
+    /// that signals seen in few sweeps still show.
+    pub fn intensities(&self, width: usize, height: usize) -> Option<Vec<Vec<f32>>> {
+        if (width, height) != (self.width, self.height) || self.hits.is_empty() {
+            return None;
+        }
+        let steady = (1.0 / (1.0 - self.decay)).ln_1p();
+        Some(
+            self.hits
+                .chunks(height)
+                .map(|column| {
+                    column
+                        .iter()
+                        .map(|&h| (h.ln_1p() / steady).min(1.0))
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+}
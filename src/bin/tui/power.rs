@@ -0,0 +1,152 @@
+// Power integrated over frequency windows, followed sweep after sweep
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use hackrf_spectrum_analyzer::sweep::Sweep;
+
+/// Maximum number of windows, each gets its own chart
+pub const MAX_WINDOWS: usize = 4;
+
+/// Level of a window now and over the samples kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerStats {
+    pub now: f32,
+    pub min: f32,
+    pub max: f32,
+    /// Mean of the power, not of the dB values
+    pub mean: f32,
+}
+
+#[derive(Debug)]
+pub struct PowerWindow {
+    pub hz_low: u64,
+    pub hz_high: u64,
+    /// Sweep time and total power in dB, oldest first
+    samples: VecDeque<(DateTime<Utc>, f32)>,
+}
+
+impl PowerWindow {
+    fn new(hz_low: u64, hz_high: u64) -> Self {
+        Self {
+            hz_low,
+            hz_high,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "{:.3}-{:.3} MHz",
+            self.hz_low as f64 / 1_000_000.0,
+            self.hz_high as f64 / 1_000_000.0
+        )
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &(DateTime<Utc>, f32)> {
+        self.samples.iter()
+    }
+
+    pub fn stats(&self) -> Option<PowerStats> {
+        let &(_, now) = self.samples.back()?;
+        let (mut min, mut max, mut sum) = (f32::INFINITY, f32::NEG_INFINITY, 0.0);
+        for &(_, db) in &self.samples {
+            min = min.min(db);
+            max = max.max(db);
+            sum += 10f64.powf(db as f64 / 10.0);
+        }
+        let mean = 10.0 * (sum / self.samples.len() as f64).log10();
+        Some(PowerStats {
+            now,
+            min,
+            max,
+            mean: mean as f32,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BandPower {
+    windows: Vec<PowerWindow>,
+    /// Samples kept per window
+    capacity: usize,
+}
+
+impl BandPower {
+    pub fn new(windows: &[(u64, u64)], capacity: usize) -> Self {
+        Self {
+            windows: windows
+                .iter()
+                .map(|&(low, high)| PowerWindow::new(low, high))
+                .collect(),
+            capacity,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PowerWindow> {
+        self.windows.iter()
+    }
+
+    /// Time of the newest sample of any window
+    pub fn newest(&self) -> Option<DateTime<Utc>> {
+        self.windows
+            .iter()
+            .filter_map(|w| w.samples.back().map(|&(t, _)| t))
+            .max()
+    }
+
+    /// Follow another window, false when there are already MAX_WINDOWS
+    pub fn add(&mut self, hz_low: u64, hz_high: u64) -> bool {
+        if self.windows.len() >= MAX_WINDOWS {
+            return false;
+        }
+        self.windows.push(PowerWindow::new(hz_low, hz_high));
+        true
+    }
+
+    pub fn remove_last(&mut self) {
+        self.windows.pop();
+    }
+
+    /// Forget the samples, keeping the windows
+    pub fn clear(&mut self) {
+        for window in &mut self.windows {
+            window.samples.clear();
+        }
+    }
+
+    pub fn update(&mut self, sweep: &Sweep) {
+        for window in &mut self.windows {
+            if let Some(db) = sweep.band_power(window.hz_low, window.hz_high) {
+                window.samples.push_back((sweep.timestamp, db));
+                if window.samples.len() > self.capacity {
+                    window.samples.pop_front();
+                }
+            }
+        }
+    }
+}
+
+/// Parse a window given as "min-max" in MHz
+pub fn parse_window(s: &str) -> Result<(u64, u64), String> {
+    let (low, high) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected MIN-MAX in MHz, got {:?}", s))?;
+    let mhz = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|_| format!("invalid frequency {:?}", v))
+    };
+    let (low, high) = (mhz(low)?, mhz(high)?);
+    if high <= low {
+        return Err(format!("{} MHz is not above {} MHz", high, low));
+    }
+    Ok((
+        (low * 1_000_000.0).round() as u64,
+        (high * 1_000_000.0).round() as u64,
+    ))
+}
@@ -0,0 +1,249 @@
+// Settings panel: radio and display settings edited while sweeping
+
+use hackrf_spectrum_analyzer::SweepParams;
+
+/// hackrf_sweep limits and steps of the gains, in dB
+const LNA_GAIN_MAX: u8 = 40;
+const LNA_GAIN_STEP: u8 = 8;
+const VGA_GAIN_MAX: u8 = 62;
+const VGA_GAIN_STEP: u8 = 2;
+
+/// Bin widths offered, within the 2445 Hz - 5 MHz hackrf_sweep accepts
+const BIN_WIDTHS: &[u32] = &[
+    2500, 5000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000, 1_000_000, 2_000_000, 5_000_000,
+];
+
+/// Step of the dB range, and the narrowest range
+const DB_STEP: f32 = 5.0;
+const MIN_DB_SPAN: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Setting {
+    LnaGain,
+    VgaGain,
+    Amp,
+    Antenna,
+    BinWidth,
+    MinDb,
+    MaxDb,
+}
+
+pub const SETTINGS: [Setting; 7] = [
+    Setting::LnaGain,
+    Setting::VgaGain,
+    Setting::Amp,
+    Setting::Antenna,
+    Setting::BinWidth,
+    Setting::MinDb,
+    Setting::MaxDb,
+];
+
+impl Setting {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::LnaGain => "LNA (IF) gain",
+            Self::VgaGain => "VGA (BB) gain",
+            Self::Amp => "RF amp",
+            Self::Antenna => "Antenna power",
+            Self::BinWidth => "Bin width",
+            Self::MinDb => "Min dB",
+            Self::MaxDb => "Max dB",
+        }
+    }
+
+    pub fn value(self, params: &SweepParams) -> String {
+        let on_off = |v: u8| if v == 1 { "ON" } else { "OFF" }.to_string();
+        match self {
+            Self::LnaGain => format!("{} dB", params.lna_gain),
+            Self::VgaGain => format!("{} dB", params.gain),
+            Self::Amp => on_off(params.amp_enable),
+            Self::Antenna => on_off(params.antenna_enable),
+            Self::BinWidth => format!("{} Hz", params.bin_width),
+            Self::MinDb => format!("{:.0} dB", params.min_db),
+            Self::MaxDb => format!("{:.0} dB", params.max_db),
+        }
+    }
+
+    /// True for the settings of hackrf_sweep, which needs a new sweep to apply them
+    pub fn restarts_sweep(self) -> bool {
+        !matches!(self, Self::MinDb | Self::MaxDb)
+    }
+
+    /// Step the setting up or down within the hackrf_sweep limits
+    pub fn adjust(self, params: &mut SweepParams, up: bool) {
+        match self {
+            Self::LnaGain => {
+                params.lna_gain = step(params.lna_gain, LNA_GAIN_STEP, LNA_GAIN_MAX, up)
+            }
+            Self::VgaGain => params.gain = step(params.gain, VGA_GAIN_STEP, VGA_GAIN_MAX, up),
+            Self::Amp => params.amp_enable = 1 - params.amp_enable.min(1),
+            Self::Antenna => params.antenna_enable = 1 - params.antenna_enable.min(1),
+            Self::BinWidth => {
+                let next = if up {
+                    BIN_WIDTHS.iter().find(|&&w| w > params.bin_width)
+                } else {
+                    BIN_WIDTHS.iter().rev().find(|&&w| w < params.bin_width)
+                };
+                if let Some(&w) = next {
+                    params.bin_width = w;
+                }
+            }
+            Self::MinDb => {
+                let db = params.min_db + if up { DB_STEP } else { -DB_STEP };
+                params.min_db = db.min(params.max_db - MIN_DB_SPAN);
+            }
+            Self::MaxDb => {
+                let db = params.max_db + if up { DB_STEP } else { -DB_STEP };
+                params.max_db = db.max(params.min_db + MIN_DB_SPAN);
+            }
+        }
+    }
+}
+
+// Next multiple of the step, so that off-step values from the command line snap back in line
+fn step(value: u8, step: u8, max: u8, up: bool) -> u8 {
+    if up {
+        ((value as u16 / step as u16 + 1) * step as u16).min((max / step * step) as u16) as u8
+    } else if !value.is_multiple_of(step) {
+        value / step * step
+    } else {
+        value.saturating_sub(step)
+    }
+}
+
+/// The open settings panel
+#[derive(Debug, Default)]
+pub struct SettingsPanel {
+    pub selected: usize,
+}
+
+impl SettingsPanel {
+    pub fn setting(&self) -> Setting {
+        SETTINGS[self.selected]
+    }
+
+    pub fn select(&mut self, down: bool) {
+        self.selected = if down {
+            (self.selected + 1) % SETTINGS.len()
+        } else {
+            (self.selected + SETTINGS.len() - 1) % SETTINGS.len()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn params() -> SweepParams {
+        SweepParams::parse_from(["tui"])
+    }
+
+    #[test]
+    fn gains_step_by_their_hackrf_step() {
+        assert_eq!(step(16, LNA_GAIN_STEP, LNA_GAIN_MAX, true), 24);
+        assert_eq!(step(16, LNA_GAIN_STEP, LNA_GAIN_MAX, false), 8);
+        assert_eq!(step(14, VGA_GAIN_STEP, VGA_GAIN_MAX, true), 16);
+        assert_eq!(step(14, VGA_GAIN_STEP, VGA_GAIN_MAX, false), 12);
+    }
+
+    #[test]
+    fn off_step_gains_snap_to_the_next_step() {
+        assert_eq!(step(17, LNA_GAIN_STEP, LNA_GAIN_MAX, true), 24);
+        assert_eq!(step(17, LNA_GAIN_STEP, LNA_GAIN_MAX, false), 16);
+        assert_eq!(step(23, LNA_GAIN_STEP, LNA_GAIN_MAX, false), 16);
+        assert_eq!(step(13, VGA_GAIN_STEP, VGA_GAIN_MAX, true), 14);
+        assert_eq!(step(13, VGA_GAIN_STEP, VGA_GAIN_MAX, false), 12);
+    }
+
+    #[test]
+    fn gains_are_clamped_at_both_ends() {
+        assert_eq!(step(0, LNA_GAIN_STEP, LNA_GAIN_MAX, false), 0);
+        assert_eq!(step(40, LNA_GAIN_STEP, LNA_GAIN_MAX, true), 40);
+        assert_eq!(step(39, LNA_GAIN_STEP, LNA_GAIN_MAX, true), 40);
+        // above the limit, e.g. from the command line
+        assert_eq!(step(50, LNA_GAIN_STEP, LNA_GAIN_MAX, true), 40);
+        assert_eq!(step(62, VGA_GAIN_STEP, VGA_GAIN_MAX, true), 62);
+        assert_eq!(step(1, VGA_GAIN_STEP, VGA_GAIN_MAX, false), 0);
+        // a maximum off the step stops at the last step below it
+        assert_eq!(step(8, 5, 12, true), 10);
+        assert_eq!(step(10, 5, 12, true), 10);
+    }
+
+    #[test]
+    fn adjusting_a_gain_changes_only_that_gain() {
+        let mut params = params();
+        let (lna, vga) = (params.lna_gain, params.gain);
+        Setting::LnaGain.adjust(&mut params, true);
+        assert_eq!((params.lna_gain, params.gain), (lna + 8, vga));
+        Setting::VgaGain.adjust(&mut params, false);
+        assert_eq!((params.lna_gain, params.gain), (lna + 8, vga - 2));
+    }
+
+    #[test]
+    fn bin_widths_snap_to_the_offered_ones() {
+        let mut params = params();
+        params.bin_width = 100_000;
+        Setting::BinWidth.adjust(&mut params, true);
+        assert_eq!(params.bin_width, 200_000);
+
+        params.bin_width = 150_000;
+        Setting::BinWidth.adjust(&mut params, true);
+        assert_eq!(params.bin_width, 200_000);
+        params.bin_width = 150_000;
+        Setting::BinWidth.adjust(&mut params, false);
+        assert_eq!(params.bin_width, 100_000);
+    }
+
+    #[test]
+    fn bin_widths_are_clamped_at_both_ends() {
+        let mut params = params();
+        params.bin_width = 5_000_000;
+        Setting::BinWidth.adjust(&mut params, true);
+        assert_eq!(params.bin_width, 5_000_000);
+        params.bin_width = 2500;
+        Setting::BinWidth.adjust(&mut params, false);
+        assert_eq!(params.bin_width, 2500);
+        // below the smallest, only up moves it
+        params.bin_width = 2445;
+        Setting::BinWidth.adjust(&mut params, false);
+        assert_eq!(params.bin_width, 2445);
+        Setting::BinWidth.adjust(&mut params, true);
+        assert_eq!(params.bin_width, 2500);
+    }
+
+    #[test]
+    fn the_db_range_keeps_its_minimum_span() {
+        let mut params = params();
+        params.min_db = -100.0;
+        params.max_db = -80.0;
+        for _ in 0..5 {
+            Setting::MinDb.adjust(&mut params, true);
+        }
+        assert_eq!(params.min_db, -90.0);
+        for _ in 0..5 {
+            Setting::MaxDb.adjust(&mut params, false);
+        }
+        assert_eq!(params.max_db, -80.0);
+        Setting::MaxDb.adjust(&mut params, true);
+        assert_eq!(params.max_db, -75.0);
+    }
+
+    #[test]
+    fn switches_toggle_and_selection_wraps() {
+        let mut params = params();
+        params.amp_enable = 0;
+        Setting::Amp.adjust(&mut params, true);
+        assert_eq!(params.amp_enable, 1);
+        Setting::Amp.adjust(&mut params, true);
+        assert_eq!(params.amp_enable, 0);
+
+        let mut panel = SettingsPanel::default();
+        panel.select(false);
+        assert_eq!(panel.setting(), Setting::MaxDb);
+        panel.select(true);
+        assert_eq!(panel.setting(), Setting::LnaGain);
+    }
+}
@@ -0,0 +1,117 @@
+// Dumping part of the waterfall history to an image and a raw sidecar, and
+// saving and loading reference sweeps
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use hackrf_spectrum_analyzer::{
+    annotate::{annotate, WaterfallAnnotation},
+    colormap::ColorMap,
+    raw::{RawReader, RawWriter, RAW_EXTENSION},
+    sweep::Sweep,
+};
+use image::{Rgb, RgbImage};
+
+/// Write the sweeps, oldest first, to an annotated PNG and a .sweeps file in
+/// `directory`; sweeps on another frequency axis than the newest are left out
+///
+/// Returns the path of the image.
+pub fn dump(
+    directory: &Path,
+    sweeps: &[&Sweep],
+    colormap: &ColorMap,
+    (min_db, max_db): (f32, f32),
+    title: &str,
+) -> Result<PathBuf> {
+    let newest = *sweeps.last().ok_or_else(|| eyre!("no sweeps to dump"))?;
+    let sweeps: Vec<&Sweep> = sweeps
+        .iter()
+        .copied()
+        .filter(|s| {
+            s.hz_low == newest.hz_low
+                && s.hz_high == newest.hz_high
+                && s.db.len() == newest.db.len()
+        })
+        .collect();
+
+    std::fs::create_dir_all(directory)
+        .wrap_err_with(|| format!("unable to create {}", directory.display()))?;
+    let path = directory.join(format!(
+        "tui_{}.png",
+        newest.timestamp.format("%Y%m%d_%H%M%S")
+    ));
+
+    let mut raw = RawWriter::create(path.with_extension(RAW_EXTENSION), newest)?;
+    let mut pixels = Vec::with_capacity(sweeps.len() * newest.db.len() * 3);
+    for sweep in &sweeps {
+        raw.push(sweep)?;
+        for &db in &sweep.db {
+            let (r, g, b) = colormap.eval_db(db, min_db, max_db);
+            pixels.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    let img = RgbImage::from_raw(newest.db.len() as u32, sweeps.len() as u32, pixels)
+        .expect("row buffer does not match the image size");
+    let timestamps: Vec<_> = sweeps.iter().map(|s| s.timestamp).collect();
+    let title = format!(
+        "{}  {}",
+        timestamps[0].format("%Y-%m-%d %H:%M:%S UTC"),
+        title
+    );
+    let annotation = WaterfallAnnotation {
+        title: &title,
+        hz_low: newest.hz_low,
+        hz_high: newest.hz_high,
+        min_db,
+        max_db,
+    };
+    annotate(&img, 0, &annotation, &timestamps, |db| {
+        let (r, g, b) = colormap.eval_db(db, min_db, max_db);
+        Rgb([r, g, b])
+    })
+    .save(&path)
+    .wrap_err_with(|| format!("unable to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Write the reference to a one sweep .sweeps file in `directory`
+///
+/// Returns the path of the file.
+pub fn save_reference(directory: &Path, reference: &Sweep) -> Result<PathBuf> {
+    std::fs::create_dir_all(directory)
+        .wrap_err_with(|| format!("unable to create {}", directory.display()))?;
+    let path = directory.join(format!(
+        "reference_{}.{}",
+        reference.timestamp.format("%Y%m%d_%H%M%S"),
+        RAW_EXTENSION
+    ));
+    RawWriter::create(&path, reference)?.push(reference)?;
+    Ok(path)
+}
+
+/// Read a reference from a .sweeps file: the average of its sweeps, so a
+/// dumped stretch of waterfall works as well as a saved reference
+pub fn load_reference(path: &Path) -> Result<Sweep> {
+    let mut sum: Option<Sweep> = None;
+    let mut count = 0;
+    for sweep in RawReader::open(path)? {
+        let sweep = sweep.wrap_err_with(|| format!("unable to read {}", path.display()))?;
+        match &mut sum {
+            Some(sum) => {
+                for (sum, db) in sum.db.iter_mut().zip(&sweep.db) {
+                    *sum += db;
+                }
+            }
+            None => sum = Some(sweep),
+        }
+        count += 1;
+    }
+
+    let mut reference = sum.ok_or_else(|| eyre!("{} holds no sweeps", path.display()))?;
+    for db in &mut reference.db {
+        *db /= count as f32;
+    }
+    Ok(reference)
+}
@@ -0,0 +1,100 @@
+// Sweep rate, latency and the events shown next to waterfall rows
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+/// Weight of the newest sweep in the averaged interval
+const SMOOTHING: f64 = 0.2;
+/// Intervals this many times longer than the average are gaps
+const GAP_FACTOR: f64 = 3.0;
+/// Sweeps averaged before gaps are looked for
+const WARMUP_SWEEPS: u32 = 3;
+
+/// Something that happened just before a sweep
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowEvent {
+    /// Sweeps missing, with the seconds since the previous one
+    Gap(f64),
+    /// Settings changed, described briefly
+    Changed(String),
+}
+
+#[derive(Debug, Default)]
+pub struct SweepStats {
+    previous: Option<DateTime<Utc>>,
+    /// Averaged seconds between sweeps
+    interval: Option<f64>,
+    sweeps: u32,
+    latency: Option<Duration>,
+    /// Sweeps made with settings that had already changed
+    pub dropped: u64,
+}
+
+impl SweepStats {
+    /// Record a sweep and the time hackrf_sweep took for it, returns the
+    /// seconds since the previous sweep if sweeps look to be missing
+    pub fn record(&mut self, timestamp: DateTime<Utc>, latency: Duration) -> Option<f64> {
+        self.latency = Some(latency);
+        let previous = self.previous.replace(timestamp)?;
+        let seconds = (timestamp - previous).num_milliseconds() as f64 / 1000.0;
+
+        let gap = self
+            .interval
+            .filter(|_| self.sweeps >= WARMUP_SWEEPS)
+            .is_some_and(|interval| seconds > interval * GAP_FACTOR);
+        // a gap would distort the average
+        if !gap {
+            self.interval = Some(match self.interval {
+                Some(interval) => interval + (seconds - interval) * SMOOTHING,
+                None => seconds,
+            });
+            self.sweeps += 1;
+        }
+        gap.then_some(seconds)
+    }
+
+    /// Forget the previous sweep, the pause after a restart is not a gap
+    pub fn restart(&mut self) {
+        self.previous = None;
+    }
+
+    pub fn sweeps_per_second(&self) -> Option<f64> {
+        self.interval.filter(|&i| i > 0.0).map(|i| 1.0 / i)
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+}
+
+/// Events of the sweeps in the history, by sweep time
+#[derive(Debug, Default)]
+pub struct RowEvents {
+    events: VecDeque<(DateTime<Utc>, RowEvent)>,
+}
+
+impl RowEvents {
+    pub fn push(&mut self, timestamp: DateTime<Utc>, event: RowEvent) {
+        self.events.push_back((timestamp, event));
+    }
+
+    /// Drop the events of sweeps older than the history
+    pub fn trim(&mut self, oldest: DateTime<Utc>) {
+        while self.events.front().is_some_and(|(t, _)| *t < oldest) {
+            self.events.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn at(&self, timestamp: DateTime<Utc>) -> impl Iterator<Item = &RowEvent> {
+        self.events
+            .iter()
+            .filter(move |(t, _)| *t == timestamp)
+            .map(|(_, e)| e)
+    }
+}
@@ -0,0 +1,160 @@
+// Traces drawn with the live spectrum: max hold, average and a reference,
+// and the difference of sweeps to the reference
+
+use hackrf_spectrum_analyzer::sweep::Sweep;
+use ratatui::style::Color;
+
+/// Sweeps the average is taken over, older ones fade out exponentially
+const AVERAGE_SWEEPS: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceKind {
+    Live,
+    MaxHold,
+    Average,
+    Reference,
+}
+
+impl TraceKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Live => "Live",
+            Self::MaxHold => "Max hold",
+            Self::Average => "Average",
+            Self::Reference => "Reference",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Live => Color::Yellow,
+            Self::MaxHold => Color::Red,
+            Self::Average => Color::Cyan,
+            Self::Reference => Color::Magenta,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Traces {
+    max_hold: bool,
+    average: bool,
+    hold: Option<Sweep>,
+    /// Running average and the number of sweeps in it
+    mean: Option<(Sweep, u32)>,
+    reference: Option<Sweep>,
+}
+
+impl Traces {
+    pub fn max_hold(&self) -> bool {
+        self.max_hold
+    }
+
+    pub fn average(&self) -> bool {
+        self.average
+    }
+
+    /// Switch the max hold on or off, starting over in both cases
+    pub fn toggle_max_hold(&mut self) {
+        self.max_hold = !self.max_hold;
+        self.hold = None;
+    }
+
+    /// Switch the average on or off, starting over in both cases
+    pub fn toggle_average(&mut self) {
+        self.average = !self.average;
+        self.mean = None;
+    }
+
+    /// The running average, while the average is on
+    pub fn mean(&self) -> Option<&Sweep> {
+        self.mean.as_ref().map(|(s, _)| s)
+    }
+
+    /// Keep the sweep as reference, or drop the reference if there is one
+    pub fn toggle_reference(&mut self, sweep: Option<&Sweep>) {
+        self.reference = match self.reference {
+            Some(_) => None,
+            None => sweep.cloned(),
+        };
+    }
+
+    pub fn set_reference(&mut self, sweep: Sweep) {
+        self.reference = Some(sweep);
+    }
+
+    pub fn reference(&self) -> Option<&Sweep> {
+        self.reference.as_ref()
+    }
+
+    /// Whether there is a reference the sweep can be compared with, one of
+    /// the same bin width
+    pub fn compares(&self, sweep: &Sweep) -> bool {
+        self.reference.as_ref().is_some_and(|reference| {
+            (reference.hz_bin_width - sweep.hz_bin_width).abs() <= sweep.hz_bin_width * 0.01
+        })
+    }
+
+    /// The reference at the bins of the sweep, bins it does not cover take
+    /// the level of the sweep
+    pub fn aligned_reference(&self, sweep: &Sweep) -> Option<Sweep> {
+        let reference = self.reference.as_ref().filter(|_| self.compares(sweep))?;
+        let db = sweep
+            .db
+            .iter()
+            .enumerate()
+            .map(|(i, &db)| {
+                reference
+                    .bin_at(sweep.bin_hz(i))
+                    .map_or(db, |bin| reference.db[bin])
+            })
+            .collect();
+        Some(Sweep { db, ..*sweep })
+    }
+
+    /// Start the max hold and average over, after the range changed
+    pub fn restart(&mut self) {
+        self.hold = None;
+        self.mean = None;
+    }
+
+    pub fn update(&mut self, sweep: &Sweep) {
+        if self.max_hold {
+            match &mut self.hold {
+                Some(hold) if same_bins(hold, sweep) => {
+                    for (held, &db) in hold.db.iter_mut().zip(&sweep.db) {
+                        *held = held.max(db);
+                    }
+                }
+                hold => *hold = Some(sweep.clone()),
+            }
+        }
+
+        if self.average {
+            match &mut self.mean {
+                Some((mean, count)) if same_bins(mean, sweep) => {
+                    *count = (*count + 1).min(AVERAGE_SWEEPS);
+                    for (mean, &db) in mean.db.iter_mut().zip(&sweep.db) {
+                        *mean += (db - *mean) / *count as f32;
+                    }
+                }
+                mean => *mean = Some((sweep.clone(), 1)),
+            }
+        }
+    }
+
+    /// The traces to draw besides the live one
+    pub fn iter(&self) -> impl Iterator<Item = (TraceKind, &Sweep)> {
+        [
+            self.hold.as_ref().map(|s| (TraceKind::MaxHold, s)),
+            self.mean.as_ref().map(|(s, _)| (TraceKind::Average, s)),
+            self.reference.as_ref().map(|s| (TraceKind::Reference, s)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+fn same_bins(a: &Sweep, b: &Sweep) -> bool {
+    a.hz_low == b.hz_low && a.db.len() == b.db.len()
+}
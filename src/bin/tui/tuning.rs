@@ -0,0 +1,266 @@
+// Retuning the sweep: zooming, panning and the zoom history
+
+use std::sync::{Arc, Mutex};
+
+use hackrf_spectrum_analyzer::SweepParams;
+
+/// Frequency range accepted by hackrf_sweep, in MHz
+const FREQ_LIMIT_MHZ: (u32, u32) = (0, 7250);
+/// Narrowest span that can be zoomed into, in MHz; hackrf_sweep only takes
+/// whole MHz ranges
+const MIN_SPAN_MHZ: u32 = 1;
+/// Bin widths accepted by hackrf_sweep, in Hz
+const BIN_WIDTH_LIMIT_HZ: (u32, u32) = (2445, 5_000_000);
+
+/// Sweep settings shared with the sweep thread, which picks up changes on its
+/// next sweep and tags each sweep with the generation it was started with
+#[derive(Debug, Clone)]
+pub struct SweepSource {
+    pub params: SweepParams,
+    pub generation: u64,
+}
+
+impl SweepSource {
+    pub fn shared(params: SweepParams) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            params,
+            generation: 0,
+        }))
+    }
+}
+
+/// Frequency range and resolution of a sweep
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    pub min_freq: u32,
+    pub max_freq: u32,
+    pub bin_width: u32,
+}
+
+impl Tuning {
+    pub fn of(params: &SweepParams) -> Self {
+        Self {
+            min_freq: params.min_freq,
+            max_freq: params.max_freq,
+            bin_width: params.bin_width,
+        }
+    }
+
+    pub fn apply(&self, params: &mut SweepParams) {
+        params.min_freq = self.min_freq;
+        params.max_freq = self.max_freq;
+        params.bin_width = self.bin_width;
+    }
+
+    pub fn span(&self) -> u32 {
+        self.max_freq.saturating_sub(self.min_freq)
+    }
+
+    pub fn center(&self) -> f64 {
+        (self.min_freq + self.max_freq) as f64 / 2.0
+    }
+}
+
+/// Zoom state: the startup range and the ranges zoomed in from
+pub struct Zoom {
+    full: Tuning,
+    /// Bins per sweep at the full span, kept when zooming
+    bins: f64,
+    history: Vec<Tuning>,
+}
+
+impl Zoom {
+    pub fn new(full: Tuning) -> Self {
+        Self {
+            full,
+            bins: (full.span() as f64 * 1_000_000.0 / full.bin_width as f64).max(1.0),
+            history: Vec::new(),
+        }
+    }
+
+    /// Keep the resolution of the given tuning, e.g. after changing its bin width
+    pub fn set_resolution(&mut self, current: Tuning) {
+        self.bins = (current.span() as f64 * 1_000_000.0 / current.bin_width as f64).max(1.0);
+        self.full.bin_width = self
+            .tuning(self.full.min_freq as f64, self.full.max_freq as f64)
+            .bin_width;
+    }
+
+    /// Number of ranges that can be gone back to
+    pub fn depth(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Zoom by a factor (above 1 zooms in) keeping `center_mhz` in place; an
+    /// empty range has no position to keep and is returned unchanged
+    pub fn zoom(&mut self, current: Tuning, factor: f64, center_mhz: f64) -> Tuning {
+        if current.span() == 0 {
+            return current;
+        }
+        let span = current.span() as f64 / factor;
+        // the center frequency stays at the same relative position on screen
+        let position = (center_mhz - current.min_freq as f64) / current.span() as f64;
+        let min = center_mhz - span * position;
+        self.push(current, self.tuning(min, min + span))
+    }
+
+    /// Zoom into the range between two frequencies
+    pub fn select(&mut self, current: Tuning, from_mhz: f64, to_mhz: f64) -> Tuning {
+        let tuning = self.tuning(from_mhz.min(to_mhz), from_mhz.max(to_mhz));
+        self.push(current, tuning)
+    }
+
+    /// Move by a fraction of the span, negative to the left; not recorded in the history
+    pub fn pan(&self, current: Tuning, fraction: f64) -> Tuning {
+        let shift = (current.span() as f64 * fraction).round();
+        let shift = if shift == 0.0 {
+            fraction.signum()
+        } else {
+            shift
+        };
+        let span = current.span() as f64;
+        let min = (current.min_freq as f64 + shift)
+            .clamp(FREQ_LIMIT_MHZ.0 as f64, FREQ_LIMIT_MHZ.1 as f64 - span);
+        Tuning {
+            min_freq: min as u32,
+            max_freq: (min + span) as u32,
+            ..current
+        }
+    }
+
+    /// Go back to the range before the last zoom
+    pub fn back(&mut self) -> Option<Tuning> {
+        self.history.pop()
+    }
+
+    /// Return to the startup range
+    pub fn full(&mut self, current: Tuning) -> Tuning {
+        self.push(current, self.full)
+    }
+
+    fn push(&mut self, current: Tuning, tuning: Tuning) -> Tuning {
+        if tuning != current {
+            self.history.push(current);
+        }
+        tuning
+    }
+
+    // Whole MHz range within the hackrf limits, with the bin width that keeps
+    // the number of bins of the full span
+    fn tuning(&self, min_mhz: f64, max_mhz: f64) -> Tuning {
+        let (lo, hi) = FREQ_LIMIT_MHZ;
+        let span = (max_mhz - min_mhz)
+            .round()
+            .clamp(MIN_SPAN_MHZ as f64, (hi - lo) as f64);
+        let min = min_mhz.round().clamp(lo as f64, hi as f64 - span);
+        let bin_width = (span * 1_000_000.0 / self.bins)
+            .round()
+            .clamp(BIN_WIDTH_LIMIT_HZ.0 as f64, BIN_WIDTH_LIMIT_HZ.1 as f64);
+
+        Tuning {
+            min_freq: min as u32,
+            max_freq: (min + span) as u32,
+            bin_width: bin_width as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuning(min_freq: u32, max_freq: u32, bin_width: u32) -> Tuning {
+        Tuning {
+            min_freq,
+            max_freq,
+            bin_width,
+        }
+    }
+
+    #[test]
+    fn zoom_keeps_the_center_and_the_number_of_bins() {
+        let full = tuning(2400, 2500, 100_000);
+        let mut zoom = Zoom::new(full);
+        let zoomed = zoom.zoom(full, 2.0, full.center());
+        assert_eq!(zoomed, tuning(2425, 2475, 50_000));
+        assert_eq!(zoom.depth(), 1);
+
+        // the frequency under the pointer stays at the same place
+        let zoomed = zoom.zoom(full, 4.0, 2420.0);
+        assert_eq!(zoomed, tuning(2415, 2440, 25_000));
+    }
+
+    #[test]
+    fn zoom_stays_within_the_hackrf_limits() {
+        let full = tuning(2400, 2500, 100_000);
+        let mut zoom = Zoom::new(full);
+
+        // whole MHz, never narrower than the minimum span
+        let narrow = zoom.zoom(tuning(2400, 2402, 2_000), 8.0, 2401.0);
+        assert_eq!(narrow.span(), MIN_SPAN_MHZ);
+        assert_eq!(narrow.bin_width, BIN_WIDTH_LIMIT_HZ.0);
+
+        // never past the top of the range nor wider than all of it
+        let top = zoom.zoom(tuning(7200, 7250, 100_000), 0.25, 7225.0);
+        assert_eq!((top.min_freq, top.max_freq), (7050, 7250));
+        let all = zoom.zoom(full, 1e-3, full.center());
+        assert_eq!((all.min_freq, all.max_freq), FREQ_LIMIT_MHZ);
+        assert_eq!(all.bin_width, BIN_WIDTH_LIMIT_HZ.1);
+    }
+
+    #[test]
+    fn empty_range_is_not_zoomed() {
+        let empty = tuning(2400, 2400, 100_000);
+        let mut zoom = Zoom::new(empty);
+        assert_eq!(zoom.zoom(empty, 2.0, 2400.0), empty);
+        assert_eq!(zoom.zoom(empty, 0.5, 2400.0), empty);
+        assert_eq!(zoom.depth(), 0);
+    }
+
+    #[test]
+    fn select_orders_its_ends() {
+        let full = tuning(2400, 2500, 100_000);
+        let mut zoom = Zoom::new(full);
+        assert_eq!(
+            zoom.select(full, 2460.2, 2440.4),
+            tuning(2440, 2460, 20_000)
+        );
+    }
+
+    #[test]
+    fn pan_moves_at_least_one_mhz_and_stops_at_the_limits() {
+        let zoom = Zoom::new(tuning(2400, 2500, 100_000));
+        assert_eq!(
+            zoom.pan(tuning(2400, 2500, 100_000), 0.25),
+            tuning(2425, 2525, 100_000)
+        );
+        assert_eq!(
+            zoom.pan(tuning(2400, 2401, 2_445), 0.1),
+            tuning(2401, 2402, 2_445)
+        );
+        assert_eq!(
+            zoom.pan(tuning(10, 110, 100_000), -0.5),
+            tuning(0, 100, 100_000)
+        );
+        assert_eq!(
+            zoom.pan(tuning(7200, 7250, 100_000), 0.5),
+            tuning(7200, 7250, 100_000)
+        );
+    }
+
+    #[test]
+    fn back_and_full_walk_the_history() {
+        let full = tuning(2400, 2500, 100_000);
+        let mut zoom = Zoom::new(full);
+        let zoomed = zoom.zoom(full, 2.0, full.center());
+        let zoomed_more = zoom.zoom(zoomed, 2.0, zoomed.center());
+        assert_eq!(zoom.full(zoomed_more), full);
+        assert_eq!(zoom.back(), Some(zoomed_more));
+        assert_eq!(zoom.back(), Some(zoomed));
+        assert_eq!(zoom.back(), Some(full));
+        assert_eq!(zoom.back(), None);
+        // going to where it already is adds nothing to go back to
+        assert_eq!(zoom.full(full), full);
+        assert_eq!(zoom.depth(), 0);
+    }
+}
@@ -0,0 +1,121 @@
+// Channel plans: the channels of a radio standard, measured sweep by sweep
+//
+// A channel plan file is a band plan file, one channel per line:
+//
+//     name, start MHz, stop MHz
+//
+// The colour a band plan line may end with is ignored. Channels keep the
+// order of the file.
+
+use std::path::Path;
+
+use color_eyre::eyre::Result;
+
+use crate::bandplan::BandPlan;
+use crate::sweep::Sweep;
+
+/// Names of the built-in channel plans, usable in place of a file path
+pub const BUILTIN_CHANNEL_PLANS: &[&str] = &["wifi24", "ble"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub start_hz: u64,
+    pub stop_hz: u64,
+}
+
+/// Power of a channel in one sweep and whether it was in use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevel {
+    /// Total power of the bins of the channel, in dB
+    pub power: f32,
+    /// Whether any bin of the channel rises above the busy threshold
+    pub busy: bool,
+}
+
+impl Channel {
+    /// Name without the standard, e.g. "6" for "WiFi 6"
+    pub fn short_name(&self) -> &str {
+        self.name.rsplit(' ').next().unwrap_or(&self.name)
+    }
+
+    /// Level of the channel in the sweep, busy when a bin rises above
+    /// `threshold_db`; None if the sweep does not cover it
+    pub fn measure(&self, sweep: &Sweep, threshold_db: f32) -> Option<ChannelLevel> {
+        let bins = sweep.band(self.start_hz, self.stop_hz)?;
+        Some(ChannelLevel {
+            power: sweep.band_power(self.start_hz, self.stop_hz)?,
+            busy: bins.iter().any(|&db| db > threshold_db),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelPlan {
+    pub name: String,
+    pub channels: Vec<Channel>,
+}
+
+impl ChannelPlan {
+    /// Load a built-in channel plan by name, or a channel plan file by path
+    pub fn load(spec: &str) -> Result<Self> {
+        if let Some(plan) = Self::builtin(spec) {
+            return Ok(plan);
+        }
+        Self::from_file(spec)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        BandPlan::from_file(path).map(Self::from)
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "wifi24" => BandPlan::builtin("wifi24").map(Self::from),
+            "ble" => Some(ble_channels()),
+            _ => None,
+        }
+    }
+}
+
+impl From<BandPlan> for ChannelPlan {
+    fn from(plan: BandPlan) -> Self {
+        Self {
+            name: plan.name,
+            channels: plan
+                .bands
+                .into_iter()
+                .map(|band| Channel {
+                    name: band.name,
+                    start_hz: band.start_hz,
+                    stop_hz: band.stop_hz,
+                })
+                .collect(),
+        }
+    }
+}
+
+// Channels 0-39 by index: the advertising channels 37, 38 and 39 sit at
+// 2402, 2426 and 2480 MHz, the data channels fill the 2 MHz slots between
+fn ble_channels() -> ChannelPlan {
+    let channels = (0..40)
+        .map(|index: u64| {
+            let center_mhz = match index {
+                37 => 2402,
+                38 => 2426,
+                39 => 2480,
+                0..=10 => 2404 + 2 * index,
+                _ => 2428 + 2 * (index - 11),
+            };
+            Channel {
+                name: format!("BLE {}", index),
+                start_hz: (center_mhz - 1) * 1_000_000,
+                stop_hz: (center_mhz + 1) * 1_000_000,
+            }
+        })
+        .collect();
+    ChannelPlan {
+        name: "ble".to_string(),
+        channels,
+    }
+}
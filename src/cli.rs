@@ -1,6 +1,17 @@
+use std::path::PathBuf;
+
 use clap::Parser;
+use color_eyre::eyre::Result;
 use log::info;
 
+use crate::autoscale::AutoScale;
+use crate::colormap::ColorMap;
+
+/// Number of sweeps the automatic dB range is estimated from
+const AUTO_DB_WINDOW: usize = 10;
+/// How fast a tracking automatic dB range follows the signal, per sweep
+const AUTO_DB_TRACKING_RATE: f32 = 0.05;
+
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct SweepParams {
@@ -39,10 +50,75 @@ pub struct SweepParams {
     /// Minimum dB value for visualization
     #[arg(long, default_value_t = -100.0)]
     pub min_db: f32,
+
+    /// Pick the dB range automatically from the noise floor and peaks of the first sweeps
+    #[arg(long)]
+    pub auto_db: bool,
+
+    /// With --auto-db, keep slowly following the noise floor and peaks instead of locking the range
+    #[arg(long, requires = "auto_db")]
+    pub auto_db_track: bool,
+
+    /// Colour map: a colorous gradient name (inferno, viridis, turbo, ...) or a gradient file
+    #[arg(long, default_value = "inferno")]
+    pub colormap: String,
+
+    /// Colour map gamma, below 1 brightens weak signals
+    #[arg(long, default_value_t = 1.0)]
+    pub gamma: f32,
+
+    /// Colour map contrast around the middle of the dB range
+    #[arg(long, default_value_t = 1.0)]
+    pub contrast: f32,
+
+    /// Band plan to overlay, either a built-in (ism, wifi24, wifi5, ble, lte) or a file path; can be repeated
+    #[arg(long = "band-plan")]
+    pub band_plans: Vec<String>,
+
+    /// Frequency allocation CSV used to annotate detected peaks
+    #[arg(long)]
+    pub allocations: Option<PathBuf>,
+
+    /// Minimum height in dB above the noise floor for a bin to be reported as a peak
+    #[arg(long, default_value_t = 15.0)]
+    pub peak_threshold: f32,
 }
 
 impl SweepParams {
+    /// The colour map selected on the command line, with gamma and contrast applied
+    pub fn load_colormap(&self) -> Result<ColorMap> {
+        Ok(ColorMap::load(&self.colormap)?.with_adjustments(self.contrast, self.gamma))
+    }
+
+    /// The automatic dB range estimator selected on the command line, if any
+    pub fn autoscale(&self) -> Option<AutoScale> {
+        self.auto_db.then(|| {
+            let tracking = self.auto_db_track.then_some(AUTO_DB_TRACKING_RATE);
+            AutoScale::new(AUTO_DB_WINDOW, tracking)
+        })
+    }
+
+    /// One line description of the radio settings, used as image title
+    pub fn summary(&self) -> String {
+        format!(
+            "{}-{} MHz, {} Hz bins, IF {} dB, BB {} dB, RF AMP {}",
+            self.min_freq,
+            self.max_freq,
+            self.bin_width,
+            self.lna_gain,
+            self.gain,
+            if self.amp_enable == 1 { "ON" } else { "OFF" }
+        )
+    }
+
+    /// Former name of `pretty_print`, kept so that existing callers still build
+    #[deprecated(note = "renamed to pretty_print")]
+    #[allow(non_snake_case)]
     pub fn PrettyPrint(&self) {
+        self.pretty_print()
+    }
+
+    pub fn pretty_print(&self) {
         info!("Sweep Parameters:");
         info!(
             "RX gain: IF {} dB, BB {} dB, RF AMP {}",
@@ -62,6 +138,27 @@ impl SweepParams {
             ((self.max_freq - self.min_freq) as f32 * 1_000_000.0) / (self.bin_width as f32)
         );
         info!("Antenna port power: {}", self.antenna_enable);
-        info!("Visualization dB range: {} to {}", self.min_db, self.max_db);
+        if self.auto_db {
+            info!(
+                "Visualization dB range: automatic{}",
+                if self.auto_db_track { ", tracking" } else { "" }
+            );
+        } else {
+            info!("Visualization dB range: {} to {}", self.min_db, self.max_db);
+        }
+        info!(
+            "Colour map: {}, gamma {}, contrast {}",
+            self.colormap, self.gamma, self.contrast
+        );
+        if !self.band_plans.is_empty() {
+            info!("Band plans: {}", self.band_plans.join(", "));
+        }
+        if let Some(allocations) = &self.allocations {
+            info!("Allocations: {}", allocations.display());
+        }
+        info!(
+            "Peak threshold: {} dB above noise floor",
+            self.peak_threshold
+        );
     }
 }
@@ -0,0 +1,342 @@
+// Colour maps shared by the TUI and the image output
+//
+// A colour map is either one of the colorous gradients, by name, or a
+// gradient file with one colour stop per line:
+//
+//     [position 0.0-1.0,] #rrggbb
+//
+// Stops without a position are spread evenly. Empty lines and lines starting
+// with '//' are ignored.
+
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+use crate::bandplan::parse_hex_color;
+
+/// All the colorous gradients, by name
+pub const BUILTIN_COLORMAPS: &[(&str, colorous::Gradient)] = &[
+    ("inferno", colorous::INFERNO),
+    ("magma", colorous::MAGMA),
+    ("plasma", colorous::PLASMA),
+    ("viridis", colorous::VIRIDIS),
+    ("cividis", colorous::CIVIDIS),
+    ("turbo", colorous::TURBO),
+    ("warm", colorous::WARM),
+    ("cool", colorous::COOL),
+    ("cubehelix", colorous::CUBEHELIX),
+    ("greys", colorous::GREYS),
+    ("blues", colorous::BLUES),
+    ("greens", colorous::GREENS),
+    ("oranges", colorous::ORANGES),
+    ("purples", colorous::PURPLES),
+    ("reds", colorous::REDS),
+    ("blue-green", colorous::BLUE_GREEN),
+    ("blue-purple", colorous::BLUE_PURPLE),
+    ("green-blue", colorous::GREEN_BLUE),
+    ("orange-red", colorous::ORANGE_RED),
+    ("purple-blue-green", colorous::PURPLE_BLUE_GREEN),
+    ("purple-blue", colorous::PURPLE_BLUE),
+    ("purple-red", colorous::PURPLE_RED),
+    ("red-purple", colorous::RED_PURPLE),
+    ("yellow-green-blue", colorous::YELLOW_GREEN_BLUE),
+    ("yellow-green", colorous::YELLOW_GREEN),
+    ("yellow-orange-brown", colorous::YELLOW_ORANGE_BROWN),
+    ("yellow-orange-red", colorous::YELLOW_ORANGE_RED),
+    ("brown-green", colorous::BROWN_GREEN),
+    ("purple-green", colorous::PURPLE_GREEN),
+    ("pink-green", colorous::PINK_GREEN),
+    ("purple-orange", colorous::PURPLE_ORANGE),
+    ("red-blue", colorous::RED_BLUE),
+    ("red-grey", colorous::RED_GREY),
+    ("red-yellow-blue", colorous::RED_YELLOW_BLUE),
+    ("red-yellow-green", colorous::RED_YELLOW_GREEN),
+    ("spectral", colorous::SPECTRAL),
+    ("rainbow", colorous::RAINBOW),
+    ("sinebow", colorous::SINEBOW),
+];
+
+#[derive(Clone)]
+enum Gradient {
+    Builtin(colorous::Gradient),
+    /// Colour stops sorted by position
+    Custom(Vec<(f32, (u8, u8, u8))>),
+}
+
+/// A gradient with contrast and gamma applied on top
+#[derive(Clone)]
+pub struct ColorMap {
+    name: String,
+    gradient: Gradient,
+    /// Slope around the middle of the range, above 1 increases contrast
+    pub contrast: f32,
+    /// Exponent applied after contrast, below 1 brightens weak signals
+    pub gamma: f32,
+}
+
+impl ColorMap {
+    /// Load a built-in colour map by name, or a gradient file by path
+    pub fn load(spec: &str) -> Result<Self> {
+        if let Some(map) = Self::builtin(spec) {
+            return Ok(map);
+        }
+        Self::from_file(spec)
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTIN_COLORMAPS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(n, gradient)| Self::new(n, Gradient::Builtin(gradient)))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("unable to read colour map {}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::parse(&name, &contents)
+            .wrap_err_with(|| format!("invalid colour map {}", path.display()))
+    }
+
+    pub fn parse(name: &str, contents: &str) -> Result<Self> {
+        let mut stops: Vec<(Option<f32>, (u8, u8, u8))> = Vec::new();
+
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let (position, color) = match line.split_once(',') {
+                Some((p, c)) => (
+                    Some(
+                        p.trim()
+                            .parse::<f32>()
+                            .wrap_err_with(|| format!("line {}: bad position", n + 1))?,
+                    ),
+                    c.trim(),
+                ),
+                None => (None, line),
+            };
+            let color = parse_hex_color(color)
+                .ok_or_else(|| eyre!("line {}: bad colour {:?}", n + 1, color))?;
+            stops.push((position, color));
+        }
+
+        if stops.len() < 2 {
+            return Err(eyre!("a gradient needs at least two colours"));
+        }
+
+        let last = (stops.len() - 1) as f32;
+        let mut stops: Vec<(f32, (u8, u8, u8))> = stops
+            .into_iter()
+            .enumerate()
+            .map(|(i, (p, c))| (p.unwrap_or(i as f32 / last).clamp(0.0, 1.0), c))
+            .collect();
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        Ok(Self::new(name, Gradient::Custom(stops)))
+    }
+
+    fn new(name: &str, gradient: Gradient) -> Self {
+        Self {
+            name: name.to_string(),
+            gradient,
+            contrast: 1.0,
+            gamma: 1.0,
+        }
+    }
+
+    pub fn with_adjustments(mut self, contrast: f32, gamma: f32) -> Self {
+        self.contrast = contrast;
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The next built-in colour map, keeping contrast and gamma
+    pub fn next_builtin(&self) -> Self {
+        self.step_builtin(1)
+    }
+
+    /// The previous built-in colour map, keeping contrast and gamma
+    pub fn previous_builtin(&self) -> Self {
+        self.step_builtin(BUILTIN_COLORMAPS.len() - 1)
+    }
+
+    fn step_builtin(&self, step: usize) -> Self {
+        let current = BUILTIN_COLORMAPS
+            .iter()
+            .position(|(n, _)| *n == self.name)
+            .unwrap_or(BUILTIN_COLORMAPS.len() - 1);
+        let (name, gradient) = BUILTIN_COLORMAPS[(current + step) % BUILTIN_COLORMAPS.len()];
+        Self::new(name, Gradient::Builtin(gradient)).with_adjustments(self.contrast, self.gamma)
+    }
+
+    /// Colour of a normalized value, 0.0 (weakest) to 1.0 (strongest)
+    pub fn eval(&self, value: f32) -> (u8, u8, u8) {
+        let value = if value.is_finite() { value } else { 0.0 };
+        let value = ((value - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0);
+        let value = value.powf(self.gamma);
+
+        match &self.gradient {
+            Gradient::Builtin(gradient) => {
+                let c = gradient.eval_continuous(value as f64);
+                (c.r, c.g, c.b)
+            }
+            Gradient::Custom(stops) => eval_stops(stops, value),
+        }
+    }
+
+    /// Colour of a dB value within the [min_db, max_db] range
+    pub fn eval_db(&self, db: f32, min_db: f32, max_db: f32) -> (u8, u8, u8) {
+        self.eval((db - min_db) / (max_db - min_db))
+    }
+}
+
+fn eval_stops(stops: &[(f32, (u8, u8, u8))], value: f32) -> (u8, u8, u8) {
+    let upper = stops.partition_point(|(p, _)| *p < value);
+    if upper == 0 {
+        return stops[0].1;
+    }
+    if upper == stops.len() {
+        return stops[stops.len() - 1].1;
+    }
+
+    let (p0, c0) = stops[upper - 1];
+    let (p1, c1) = stops[upper];
+    let t = if p1 > p0 {
+        (value - p0) / (p1 - p0)
+    } else {
+        0.0
+    };
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    (lerp(c0.0, c1.0), lerp(c0.1, c1.1), lerp(c0.2, c1.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_white() -> ColorMap {
+        ColorMap::parse("bw", "#000000\n#ffffff").unwrap()
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_number() {
+        let error = |contents: &str| match ColorMap::parse("bad", contents) {
+            Ok(_) => panic!("{:?} parsed", contents),
+            Err(e) => e.to_string(),
+        };
+        assert_eq!(error("#000000\nhalf, #ffffff"), "line 2: bad position");
+        assert_eq!(
+            error("// comment\n#000000\n0.5, #ffff\n"),
+            "line 3: bad colour \"#ffff\""
+        );
+        assert_eq!(
+            error("#000000\n\n// only one\n"),
+            "a gradient needs at least two colours"
+        );
+    }
+
+    #[test]
+    fn stops_without_a_position_are_spread_evenly() {
+        let map = ColorMap::parse("rgb", "#ff0000\n\n// green\n#00ff00\n#0000ff\n").unwrap();
+        assert_eq!(map.name(), "rgb");
+        assert_eq!(map.eval(0.0), (255, 0, 0));
+        assert_eq!(map.eval(0.5), (0, 255, 0));
+        assert_eq!(map.eval(1.0), (0, 0, 255));
+    }
+
+    #[test]
+    fn interpolation_stops_at_and_between_stops() {
+        // positions out of order, sorted on parsing
+        let map = ColorMap::parse("custom", "1.0, #0000ff\n0.0, #000000\n0.25, #ff0000").unwrap();
+        assert_eq!(map.eval(0.0), (0, 0, 0));
+        assert_eq!(map.eval(0.25), (255, 0, 0));
+        assert_eq!(map.eval(1.0), (0, 0, 255));
+        assert_eq!(map.eval(0.125), (128, 0, 0));
+        assert_eq!(map.eval(0.625), (128, 0, 128));
+    }
+
+    #[test]
+    fn values_outside_the_range_take_the_end_colours() {
+        let map = black_white();
+        assert_eq!(map.eval(-1.0), (0, 0, 0));
+        assert_eq!(map.eval(2.0), (255, 255, 255));
+        assert_eq!(map.eval(f32::NAN), (0, 0, 0));
+
+        // stops not covering the whole range
+        let map = ColorMap::parse("inner", "0.25, #ff0000\n0.75, #0000ff").unwrap();
+        assert_eq!(map.eval(0.1), (255, 0, 0));
+        assert_eq!(map.eval(0.9), (0, 0, 255));
+    }
+
+    #[test]
+    fn gamma_and_contrast_keep_the_ends_of_the_range() {
+        for (contrast, gamma) in [(1.0, 0.5), (1.0, 2.0), (2.0, 1.0), (0.5, 1.0)] {
+            let map = black_white().with_adjustments(contrast, gamma);
+            let (low, high) = (map.eval(0.0), map.eval(1.0));
+            if contrast >= 1.0 {
+                assert_eq!(low, (0, 0, 0), "contrast {} gamma {}", contrast, gamma);
+                assert_eq!(
+                    high,
+                    (255, 255, 255),
+                    "contrast {} gamma {}",
+                    contrast,
+                    gamma
+                );
+            } else {
+                // lower contrast pulls the ends towards the middle
+                assert_eq!(low, (64, 64, 64));
+                assert_eq!(high, (191, 191, 191));
+            }
+        }
+    }
+
+    #[test]
+    fn gamma_and_contrast_move_the_middle_of_the_range() {
+        assert_eq!(black_white().eval(0.25), (64, 64, 64));
+        // gamma below 1 brightens weak values
+        let map = black_white().with_adjustments(1.0, 0.5);
+        assert_eq!(map.eval(0.25), (128, 128, 128));
+        // contrast saturates the ends sooner, leaving the middle alone
+        let map = black_white().with_adjustments(2.0, 1.0);
+        assert_eq!(map.eval(0.25), (0, 0, 0));
+        assert_eq!(map.eval(0.5), (128, 128, 128));
+        assert_eq!(map.eval(0.75), (255, 255, 255));
+    }
+
+    #[test]
+    fn db_values_map_onto_the_range() {
+        let map = black_white();
+        assert_eq!(map.eval_db(-100.0, -100.0, -20.0), (0, 0, 0));
+        assert_eq!(map.eval_db(-20.0, -100.0, -20.0), (255, 255, 255));
+        assert_eq!(map.eval_db(-60.0, -100.0, -20.0), (128, 128, 128));
+        assert_eq!(map.eval_db(-120.0, -100.0, -20.0), (0, 0, 0));
+    }
+
+    #[test]
+    fn builtin_maps_are_found_by_name_and_cycled() {
+        let map = ColorMap::load("viridis")
+            .unwrap()
+            .with_adjustments(1.5, 0.8);
+        assert_eq!(map.name(), "viridis");
+        let next = map.next_builtin();
+        assert_eq!(next.name(), "cividis");
+        assert_eq!((next.contrast, next.gamma), (1.5, 0.8));
+        assert_eq!(next.previous_builtin().name(), "viridis");
+        assert_eq!(
+            ColorMap::builtin("sinebow").unwrap().next_builtin().name(),
+            "inferno"
+        );
+        assert!(ColorMap::builtin("nope").is_none());
+    }
+}
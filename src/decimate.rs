@@ -0,0 +1,318 @@
+// Time decimation: combine the sweeps of each interval into a single row,
+// the time counterpart of `Sweep::downscale` along frequency
+
+use std::str::FromStr;
+
+use chrono::TimeDelta;
+
+use crate::sweep::{DownscaleMode, Sweep};
+
+/// How the values of a bin are combined across sweeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CombineMode {
+    Max,
+    Mean,
+    /// The value below which the given percentage (0-100) of the values fall
+    Percentile(f32),
+}
+
+impl From<DownscaleMode> for CombineMode {
+    fn from(mode: DownscaleMode) -> Self {
+        match mode {
+            DownscaleMode::Peak => CombineMode::Max,
+            DownscaleMode::Average => CombineMode::Mean,
+        }
+    }
+}
+
+impl FromStr for CombineMode {
+    type Err = String;
+
+    /// "max", "mean", or "p" followed by a percentile, e.g. "p95"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" | "peak" => Ok(CombineMode::Max),
+            "mean" | "average" => Ok(CombineMode::Mean),
+            _ => s
+                .strip_prefix('p')
+                .and_then(|p| p.parse::<f32>().ok())
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(CombineMode::Percentile)
+                .ok_or_else(|| format!("expected max, mean or pNN (e.g. p90), got {:?}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for CombineMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CombineMode::Max => write!(f, "max"),
+            CombineMode::Mean => write!(f, "mean"),
+            CombineMode::Percentile(p) => write!(f, "p{}", p),
+        }
+    }
+}
+
+/// Combine sweeps of the same span bin by bin, the result takes the time of the first one
+pub fn combine(sweeps: &[Sweep], mode: CombineMode) -> Option<Sweep> {
+    let p = match mode {
+        CombineMode::Max => return Sweep::aggregate(sweeps, DownscaleMode::Peak),
+        CombineMode::Mean => return Sweep::aggregate(sweeps, DownscaleMode::Average),
+        CombineMode::Percentile(p) => p,
+    };
+    let first = sweeps.first()?;
+    let bins = sweeps.iter().map(|s| s.db.len()).min().unwrap_or(0);
+
+    let mut values = Vec::with_capacity(sweeps.len());
+    let db = (0..bins)
+        .map(|i| {
+            values.clear();
+            values.extend(sweeps.iter().map(|s| s.db[i]));
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let idx = ((values.len() - 1) as f32 * p / 100.0).round() as usize;
+            values[idx]
+        })
+        .collect();
+
+    Some(Sweep { db, ..*first })
+}
+
+// The sweeps of the current interval so far
+enum Bucket {
+    /// Running maximum or sum of each bin, on the axis and time of the first
+    /// sweep, and the number of sweeps in it
+    Running { row: Sweep, count: u32 },
+    /// Every sweep, a percentile needs all the values of a bin
+    Buffered(Vec<Sweep>),
+}
+
+impl Bucket {
+    fn new(sweep: Sweep, mode: CombineMode) -> Self {
+        match mode {
+            CombineMode::Max | CombineMode::Mean => Bucket::Running {
+                row: sweep,
+                count: 1,
+            },
+            CombineMode::Percentile(_) => Bucket::Buffered(vec![sweep]),
+        }
+    }
+
+    fn first(&self) -> &Sweep {
+        match self {
+            Bucket::Running { row, .. } => row,
+            Bucket::Buffered(sweeps) => &sweeps[0],
+        }
+    }
+
+    fn add(&mut self, sweep: Sweep, mode: CombineMode) {
+        match self {
+            Bucket::Running { row, count } => {
+                for (acc, &db) in row.db.iter_mut().zip(&sweep.db) {
+                    match mode {
+                        CombineMode::Mean => *acc += db,
+                        _ => *acc = acc.max(db),
+                    }
+                }
+                *count += 1;
+            }
+            Bucket::Buffered(sweeps) => sweeps.push(sweep),
+        }
+    }
+
+    fn combine(self, mode: CombineMode) -> Option<Sweep> {
+        match self {
+            Bucket::Running { mut row, count } => {
+                if mode == CombineMode::Mean {
+                    for acc in &mut row.db {
+                        *acc /= count as f32;
+                    }
+                }
+                Some(row)
+            }
+            Bucket::Buffered(sweeps) => combine(&sweeps, mode),
+        }
+    }
+}
+
+/// Collects sweeps into wall-clock aligned intervals and emits one combined sweep per interval
+///
+/// Max and mean only keep one running row per interval, percentiles keep
+/// every sweep of the interval until it closes.
+pub struct TimeDecimator {
+    interval_ms: i64,
+    mode: CombineMode,
+    bucket: Option<Bucket>,
+}
+
+impl TimeDecimator {
+    pub fn new(interval: TimeDelta, mode: CombineMode) -> Self {
+        Self {
+            interval_ms: interval.num_milliseconds().max(1),
+            mode,
+            bucket: None,
+        }
+    }
+
+    pub fn mode(&self) -> CombineMode {
+        self.mode
+    }
+
+    /// Add a sweep, returning the combined previous interval once a sweep of a new one arrives
+    pub fn push(&mut self, sweep: Sweep) -> Option<Sweep> {
+        let same_bucket = self.bucket.as_ref().is_some_and(|bucket| {
+            let first = bucket.first();
+            self.interval_of(first) == self.interval_of(&sweep)
+                && first.hz_low == sweep.hz_low
+                && first.db.len() == sweep.db.len()
+        });
+
+        if same_bucket {
+            if let Some(bucket) = &mut self.bucket {
+                bucket.add(sweep, self.mode);
+            }
+            return None;
+        }
+        let done = self.flush();
+        self.bucket = Some(Bucket::new(sweep, self.mode));
+        done
+    }
+
+    /// Combine and return whatever is pending
+    pub fn flush(&mut self) -> Option<Sweep> {
+        self.bucket.take()?.combine(self.mode)
+    }
+
+    fn interval_of(&self, sweep: &Sweep) -> i64 {
+        sweep
+            .timestamp
+            .timestamp_millis()
+            .div_euclid(self.interval_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    fn sweep(millis: i64, db: &[f32]) -> Sweep {
+        Sweep {
+            hz_low: 100_000_000,
+            hz_high: 100_000_000 + db.len() as u64 * 1_000_000,
+            hz_bin_width: 1_000_000.0,
+            db: db.to_vec(),
+            timestamp: DateTime::from_timestamp_millis(millis).unwrap(),
+        }
+    }
+
+    // The rows a decimator emits for the sweeps, including the last interval
+    fn decimate(interval_ms: i64, mode: CombineMode, sweeps: Vec<Sweep>) -> Vec<Sweep> {
+        let mut decimator = TimeDecimator::new(TimeDelta::milliseconds(interval_ms), mode);
+        let mut rows: Vec<Sweep> = sweeps
+            .into_iter()
+            .filter_map(|s| decimator.push(s))
+            .collect();
+        rows.extend(decimator.flush());
+        rows
+    }
+
+    #[test]
+    fn modes_parse_and_print() {
+        assert_eq!("max".parse(), Ok(CombineMode::Max));
+        assert_eq!("average".parse(), Ok(CombineMode::Mean));
+        assert_eq!("p90".parse(), Ok(CombineMode::Percentile(90.0)));
+        assert!("p101".parse::<CombineMode>().is_err());
+        assert!("median".parse::<CombineMode>().is_err());
+        assert_eq!(CombineMode::Percentile(99.5).to_string(), "p99.5");
+    }
+
+    #[test]
+    fn max_and_mean_combine_each_bin() {
+        let sweeps = || {
+            vec![
+                sweep(0, &[-90.0, -40.0]),
+                sweep(100, &[-60.0, -50.0]),
+                sweep(200, &[-30.0, -60.0]),
+            ]
+        };
+
+        let max = decimate(1000, CombineMode::Max, sweeps());
+        assert_eq!(max.len(), 1);
+        assert_eq!(max[0].db, [-30.0, -40.0]);
+
+        let mean = decimate(1000, CombineMode::Mean, sweeps());
+        assert_eq!(mean[0].db, [-60.0, -50.0]);
+        assert_eq!(mean[0].timestamp, sweeps()[0].timestamp);
+    }
+
+    #[test]
+    fn percentiles_pick_from_the_sorted_values() {
+        let sweeps = || {
+            (0..5)
+                .map(|i| sweep(i * 10, &[-(i as f32) * 10.0]))
+                .collect()
+        };
+        assert_eq!(
+            decimate(1000, CombineMode::Percentile(0.0), sweeps())[0].db,
+            [-40.0]
+        );
+        assert_eq!(
+            decimate(1000, CombineMode::Percentile(50.0), sweeps())[0].db,
+            [-20.0]
+        );
+        assert_eq!(
+            decimate(1000, CombineMode::Percentile(100.0), sweeps())[0].db,
+            [0.0]
+        );
+    }
+
+    #[test]
+    fn intervals_follow_the_wall_clock() {
+        let rows = decimate(
+            1000,
+            CombineMode::Max,
+            vec![
+                sweep(1_999, &[-50.0]),
+                sweep(2_000, &[-40.0]),
+                sweep(2_999, &[-30.0]),
+                sweep(3_000, &[-20.0]),
+                // a skipped interval does not produce an empty row
+                sweep(5_500, &[-10.0]),
+            ],
+        );
+        let times: Vec<i64> = rows
+            .iter()
+            .map(|r| r.timestamp.timestamp_millis())
+            .collect();
+        assert_eq!(times, [1_999, 2_000, 3_000, 5_500]);
+        let db: Vec<f32> = rows.iter().map(|r| r.db[0]).collect();
+        assert_eq!(db, [-50.0, -30.0, -20.0, -10.0]);
+    }
+
+    #[test]
+    fn a_new_span_closes_the_interval() {
+        let mut wider = sweep(100, &[-10.0, -10.0, -10.0]);
+        wider.hz_low = 50_000_000;
+        let rows = decimate(
+            1000,
+            CombineMode::Mean,
+            vec![
+                sweep(0, &[-50.0, -50.0]),
+                wider,
+                sweep(200, &[-70.0, -70.0]),
+            ],
+        );
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1].db.len(), 3);
+    }
+
+    #[test]
+    fn nothing_pending_flushes_nothing() {
+        let mut decimator = TimeDecimator::new(TimeDelta::seconds(1), CombineMode::Max);
+        assert!(decimator.flush().is_none());
+        assert!(decimator.push(sweep(0, &[-50.0])).is_none());
+        assert!(decimator.flush().is_some());
+        assert!(decimator.flush().is_none());
+    }
+}
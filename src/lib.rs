@@ -1,9 +1,19 @@
-use clap::Parser;
-use log::info;
-use std::{process::Command, sync::mpsc::channel, thread};
+use std::process::Command;
 
+pub mod allocation;
+pub mod annotate;
+pub mod autoscale;
+pub mod bandplan;
+pub mod channels;
 mod cli;
+pub mod colormap;
+pub mod decimate;
+pub mod peaks;
+pub mod raw;
+pub mod stream;
 pub mod sweep;
+pub mod ticks;
+pub mod waterfall;
 
 pub use cli::SweepParams;
 
@@ -53,11 +63,13 @@ pub fn run_sweep(params: &SweepParams) -> Sweep {
     // the hackrf_sweep command returns a number of lines
     // the complete spectrum is the merge of all the lines
 
-    let out = Command::new("sh")
-        .arg("-c")
-        .arg(command)
-        .output()
-        .expect("failed to execute process");
+    let mut sh = Command::new("sh");
+    sh.arg("-c").arg(command);
+    // in its own process group, so that Ctrl-C in the terminal stops the
+    // caller, which closes its files, and not the sweep it is waiting for
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut sh, 0);
+    let out = sh.output().expect("failed to execute process");
 
     let lines = String::from_utf8_lossy(&out.stdout);
 
@@ -0,0 +1,172 @@
+// Peak detection on a single sweep
+
+use crate::sweep::Sweep;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peak {
+    pub bin: usize,
+    pub hz: u64,
+    pub db: f32,
+}
+
+/// Median of the db values, a robust estimate of the noise floor
+pub fn noise_floor(db: &[f32]) -> f32 {
+    percentile(db, 0.5)
+}
+
+/// The value below which the given fraction (0.0-1.0) of the db values fall
+pub fn percentile(db: &[f32], fraction: f32) -> f32 {
+    if db.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = db.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let idx = ((sorted.len() - 1) as f32 * fraction.clamp(0.0, 1.0)).round() as usize;
+    sorted[idx]
+}
+
+/// Find the strongest local maxima rising at least `threshold_db` above the noise floor
+///
+/// Peaks closer than `min_separation_hz` to a stronger one are dropped,
+/// the result is sorted from the strongest to the weakest.
+pub fn find_peaks(
+    sweep: &Sweep,
+    threshold_db: f32,
+    min_separation_hz: u64,
+    max_peaks: usize,
+) -> Vec<Peak> {
+    let db = &sweep.db;
+    if db.is_empty() {
+        return Vec::new();
+    }
+
+    let threshold = noise_floor(db) + threshold_db;
+
+    let mut candidates: Vec<usize> = (0..db.len())
+        .filter(|&i| {
+            let left = if i > 0 { db[i - 1] } else { f32::NEG_INFINITY };
+            let right = db.get(i + 1).copied().unwrap_or(f32::NEG_INFINITY);
+            db[i] >= threshold && db[i] >= left && db[i] > right
+        })
+        .collect();
+    candidates.sort_by(|&a, &b| {
+        db[b]
+            .partial_cmp(&db[a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut peaks: Vec<Peak> = Vec::new();
+    for bin in candidates {
+        if peaks.len() >= max_peaks {
+            break;
+        }
+        let hz = sweep.bin_hz(bin);
+        if peaks.iter().any(|p| p.hz.abs_diff(hz) < min_separation_hz) {
+            continue;
+        }
+        peaks.push(Peak {
+            bin,
+            hz,
+            db: db[bin],
+        });
+    }
+
+    peaks
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    fn sweep(db: &[f32]) -> Sweep {
+        Sweep {
+            hz_low: 100_000_000,
+            hz_high: 100_000_000 + db.len() as u64 * 1_000_000,
+            hz_bin_width: 1_000_000.0,
+            db: db.to_vec(),
+            timestamp: DateTime::from_timestamp_millis(0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn percentile_picks_the_nearest_rank() {
+        let db = [5.0, 1.0, 4.0, 2.0, 3.0];
+        assert_eq!(percentile(&db, 0.0), 1.0);
+        assert_eq!(percentile(&db, 0.5), 3.0);
+        assert_eq!(percentile(&db, 1.0), 5.0);
+        assert_eq!(percentile(&db, 0.7), 4.0);
+    }
+
+    #[test]
+    fn percentile_clamps_the_fraction_and_handles_no_values() {
+        let db = [1.0, 2.0, 3.0];
+        assert_eq!(percentile(&db, -1.0), 1.0);
+        assert_eq!(percentile(&db, 2.0), 3.0);
+        assert_eq!(percentile(&[], 0.5), 0.0);
+        assert_eq!(noise_floor(&db), 2.0);
+    }
+
+    #[test]
+    fn only_maxima_above_the_threshold_are_peaks() {
+        // noise floor -100, peaks 8 and 12 dB above it
+        let db = [-100.0, -92.0, -100.0, -100.0, -88.0, -100.0, -100.0];
+        let peaks = find_peaks(&sweep(&db), 10.0, 0, 8);
+        assert_eq!(
+            peaks,
+            vec![Peak {
+                bin: 4,
+                hz: 104_500_000,
+                db: -88.0
+            }]
+        );
+        assert_eq!(find_peaks(&sweep(&db), 5.0, 0, 8).len(), 2);
+        assert!(find_peaks(&sweep(&[]), 5.0, 0, 8).is_empty());
+    }
+
+    #[test]
+    fn peaks_are_sorted_from_the_strongest() {
+        let db = [-100.0, -90.0, -100.0, -70.0, -100.0, -80.0, -100.0];
+        let bins: Vec<usize> = find_peaks(&sweep(&db), 5.0, 0, 8)
+            .iter()
+            .map(|p| p.bin)
+            .collect();
+        assert_eq!(bins, vec![3, 5, 1]);
+    }
+
+    #[test]
+    fn weaker_peaks_too_close_to_a_stronger_one_are_dropped() {
+        let db = [-100.0, -90.0, -100.0, -70.0, -100.0, -80.0, -100.0];
+        // bins 3 and 5 are 2 MHz apart, bins 1 and 3 as well
+        let bins: Vec<usize> = find_peaks(&sweep(&db), 5.0, 2_000_001, 8)
+            .iter()
+            .map(|p| p.bin)
+            .collect();
+        assert_eq!(bins, vec![3]);
+        let bins: Vec<usize> = find_peaks(&sweep(&db), 5.0, 2_000_000, 8)
+            .iter()
+            .map(|p| p.bin)
+            .collect();
+        assert_eq!(bins, vec![3, 5, 1]);
+    }
+
+    #[test]
+    fn at_most_max_peaks_are_returned() {
+        let db = [-100.0, -90.0, -100.0, -70.0, -100.0, -80.0, -100.0];
+        let bins: Vec<usize> = find_peaks(&sweep(&db), 5.0, 0, 2)
+            .iter()
+            .map(|p| p.bin)
+            .collect();
+        assert_eq!(bins, vec![3, 5]);
+        assert!(find_peaks(&sweep(&db), 5.0, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn a_plateau_counts_once() {
+        let db = [-100.0, -80.0, -80.0, -100.0, -100.0];
+        let peaks = find_peaks(&sweep(&db), 5.0, 0, 8);
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].bin, 2);
+    }
+}
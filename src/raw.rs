@@ -0,0 +1,294 @@
+// Raw sweep data sidecar, for re-rendering waterfalls without the hardware
+//
+// Layout, all values little endian:
+//
+//     header: b"HRSW", version u16, hz_low u64, hz_high u64, hz_bin_width f32,
+//             bins u32, db_offset f32, db_step f32
+//     row:    timestamp i64 (microseconds since the Unix epoch), bins x u16
+//
+// Each dB value is stored as (db - db_offset) / db_step rounded to a u16.
+// Rows are appended as they arrive, the row count is implied by the file size.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+use crate::sweep::Sweep;
+
+/// File extension of raw sidecars
+pub const RAW_EXTENSION: &str = "sweeps";
+
+const MAGIC: &[u8; 4] = b"HRSW";
+const VERSION: u16 = 1;
+
+/// Lowest storable value and resolution, covering -200 dB to +455 dB in 0.01 dB steps
+const DB_OFFSET: f32 = -200.0;
+const DB_STEP: f32 = 0.01;
+
+/// Frequency axis and quantisation shared by every row of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawHeader {
+    pub hz_low: u64,
+    pub hz_high: u64,
+    pub hz_bin_width: f32,
+    pub bins: u32,
+    pub db_offset: f32,
+    pub db_step: f32,
+}
+
+impl RawHeader {
+    fn for_sweep(sweep: &Sweep) -> Self {
+        Self {
+            hz_low: sweep.hz_low,
+            hz_high: sweep.hz_high,
+            hz_bin_width: sweep.hz_bin_width,
+            bins: sweep.db.len() as u32,
+            db_offset: DB_OFFSET,
+            db_step: DB_STEP,
+        }
+    }
+
+    fn write(&self, w: &mut impl Write) -> std::io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&self.hz_low.to_le_bytes())?;
+        w.write_all(&self.hz_high.to_le_bytes())?;
+        w.write_all(&self.hz_bin_width.to_le_bytes())?;
+        w.write_all(&self.bins.to_le_bytes())?;
+        w.write_all(&self.db_offset.to_le_bytes())?;
+        w.write_all(&self.db_step.to_le_bytes())
+    }
+
+    fn read(r: &mut impl Read) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(eyre!("not a raw sweep file"));
+        }
+        let version = u16::from_le_bytes(read_array(r)?);
+        if version != VERSION {
+            return Err(eyre!("unsupported raw sweep file version {}", version));
+        }
+        Ok(Self {
+            hz_low: u64::from_le_bytes(read_array(r)?),
+            hz_high: u64::from_le_bytes(read_array(r)?),
+            hz_bin_width: f32::from_le_bytes(read_array(r)?),
+            bins: u32::from_le_bytes(read_array(r)?),
+            db_offset: f32::from_le_bytes(read_array(r)?),
+            db_step: f32::from_le_bytes(read_array(r)?),
+        })
+    }
+
+    fn quantise(&self, db: f32) -> u16 {
+        if !db.is_finite() {
+            return 0;
+        }
+        ((db - self.db_offset) / self.db_step)
+            .round()
+            .clamp(0.0, u16::MAX as f32) as u16
+    }
+
+    fn dequantise(&self, q: u16) -> f32 {
+        self.db_offset + q as f32 * self.db_step
+    }
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Appends sweeps to a raw sidecar
+pub struct RawWriter {
+    file: BufWriter<File>,
+    header: RawHeader,
+}
+
+impl RawWriter {
+    /// Create the file, taking the frequency axis from the first sweep
+    pub fn create(path: impl AsRef<Path>, sweep: &Sweep) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).wrap_err_with(|| format!("unable to create {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        let header = RawHeader::for_sweep(sweep);
+        header.write(&mut file)?;
+        Ok(Self { file, header })
+    }
+
+    pub fn header(&self) -> &RawHeader {
+        &self.header
+    }
+
+    /// Append a sweep and flush it to disk; it must share the frequency axis of the file
+    pub fn push(&mut self, sweep: &Sweep) -> Result<()> {
+        if sweep.db.len() as u32 != self.header.bins {
+            return Err(eyre!(
+                "sweep has {} bins, raw file has {}",
+                sweep.db.len(),
+                self.header.bins
+            ));
+        }
+
+        let mut row = Vec::with_capacity(8 + sweep.db.len() * 2);
+        row.extend_from_slice(&sweep.timestamp.timestamp_micros().to_le_bytes());
+        for &db in &sweep.db {
+            row.extend_from_slice(&self.header.quantise(db).to_le_bytes());
+        }
+        self.file.write_all(&row)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the sweeps of a raw sidecar, one row at a time
+pub struct RawReader {
+    file: BufReader<File>,
+    header: RawHeader,
+    row: Vec<u8>,
+}
+
+impl RawReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).wrap_err_with(|| format!("unable to open {}", path.display()))?;
+        let mut file = BufReader::new(file);
+        let header =
+            RawHeader::read(&mut file).wrap_err_with(|| format!("invalid {}", path.display()))?;
+        Ok(Self {
+            file,
+            header,
+            row: vec![0u8; 8 + header.bins as usize * 2],
+        })
+    }
+
+    pub fn header(&self) -> &RawHeader {
+        &self.header
+    }
+}
+
+impl Iterator for RawReader {
+    type Item = Result<Sweep>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.file.read_exact(&mut self.row) {
+            Ok(()) => {}
+            // a truncated last row is what a logger killed mid-write leaves behind
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e.into())),
+        }
+
+        let micros = i64::from_le_bytes(self.row[..8].try_into().unwrap());
+        let timestamp = DateTime::<Utc>::from_timestamp_micros(micros).unwrap_or_default();
+        let db = self.row[8..]
+            .chunks_exact(2)
+            .map(|b| self.header.dequantise(u16::from_le_bytes([b[0], b[1]])))
+            .collect();
+
+        Some(Ok(Sweep {
+            hz_low: self.header.hz_low,
+            hz_high: self.header.hz_high,
+            hz_bin_width: self.header.hz_bin_width,
+            db,
+            timestamp,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}_{}.{}", name, std::process::id(), RAW_EXTENSION))
+    }
+
+    fn sweep(seconds: i64, db: Vec<f32>) -> Sweep {
+        Sweep {
+            hz_low: 2_400_000_000,
+            hz_high: 2_400_000_000 + db.len() as u64 * 1_000_000,
+            hz_bin_width: 1_000_000.0,
+            db,
+            timestamp: DateTime::from_timestamp(seconds, 123_456_000).unwrap(),
+        }
+    }
+
+    #[test]
+    fn sweeps_read_back_as_written() {
+        let path = temp_path("raw_round_trip");
+        let sweeps = [
+            sweep(1_700_000_000, vec![-100.0, -42.37, 3.5]),
+            sweep(1_700_000_001, vec![-99.99, f32::NEG_INFINITY, -200.0]),
+        ];
+
+        let mut writer = RawWriter::create(&path, &sweeps[0]).unwrap();
+        for s in &sweeps {
+            writer.push(s).unwrap();
+        }
+        drop(writer);
+
+        let reader = RawReader::open(&path).unwrap();
+        assert_eq!(reader.header().bins, 3);
+        assert_eq!(reader.header().hz_low, 2_400_000_000);
+        let read: Vec<Sweep> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.len(), 2);
+        for (read, written) in read.iter().zip(&sweeps) {
+            assert_eq!(read.timestamp, written.timestamp);
+            assert_eq!(read.hz_high, written.hz_high);
+            assert_eq!(read.hz_bin_width, written.hz_bin_width);
+        }
+        for (read, written) in read[0].db.iter().zip(&sweeps[0].db) {
+            assert!((read - written).abs() <= DB_STEP / 2.0 + 1e-4);
+        }
+        // values that cannot be stored come back as the lowest storable one
+        assert_eq!(read[1].db[1], DB_OFFSET);
+        assert_eq!(read[1].db[2], DB_OFFSET);
+    }
+
+    #[test]
+    fn truncated_last_row_is_dropped() {
+        let path = temp_path("raw_truncated");
+        let first = sweep(1_700_000_000, vec![-50.0; 4]);
+        let mut writer = RawWriter::create(&path, &first).unwrap();
+        writer.push(&first).unwrap();
+        writer.push(&first).unwrap();
+        drop(writer);
+
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 3).unwrap();
+
+        let rows = RawReader::open(&path).unwrap().count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn sweeps_of_another_width_are_refused() {
+        let path = temp_path("raw_width");
+        let mut writer = RawWriter::create(&path, &sweep(0, vec![-50.0; 4])).unwrap();
+        let refused = writer.push(&sweep(1, vec![-50.0; 5])).is_err();
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+        assert!(refused);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        let path = temp_path("raw_magic");
+        std::fs::write(&path, b"not a sweep file at all, just some text").unwrap();
+        let opened = RawReader::open(&path).is_ok();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!opened);
+    }
+}
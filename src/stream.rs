@@ -0,0 +1,314 @@
+// Image files written a row at a time, keeping no rows in memory
+//
+// TIFF files are uncompressed with all the rows in a single strip at the end
+// of the file. Each row is appended to the strip before the image height and
+// strip size in the header are updated, so the file is a valid image of the
+// rows written so far at any time.
+//
+// PNG needs its height before the first row, so rows are spooled to a side
+// file and compressed into the image when it is closed.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+// TIFF tags, in the ascending order the directory needs
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_X_RESOLUTION: u16 = 282;
+const TAG_Y_RESOLUTION: u16 = 283;
+const TAG_PLANAR_CONFIG: u16 = 284;
+const TAG_RESOLUTION_UNIT: u16 = 296;
+
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+const ENTRIES: u16 = 13;
+// header, entry count, entries and next directory offset
+const DIRECTORY_END: u32 = 8 + 2 + ENTRIES as u32 * 12 + 4;
+// bits per sample and the resolution, stored after the directory
+const BITS_OFFSET: u32 = DIRECTORY_END;
+const RESOLUTION_OFFSET: u32 = BITS_OFFSET + 6;
+const DATA_OFFSET: u32 = RESOLUTION_OFFSET + 8;
+
+// Offset of the value of the nth directory entry
+const fn entry_value(n: u32) -> u64 {
+    (8 + 2 + n * 12 + 8) as u64
+}
+const IMAGE_LENGTH_VALUE: u64 = entry_value(1);
+const STRIP_BYTE_COUNTS_VALUE: u64 = entry_value(8);
+
+/// RGB TIFF growing by a row at a time
+pub struct TiffStream {
+    file: File,
+    path: PathBuf,
+    width: u32,
+    rows: u32,
+}
+
+impl TiffStream {
+    /// Create the file, an image of `width` pixels and no rows yet
+    pub fn create(path: &Path, width: u32) -> Result<Self> {
+        let file =
+            File::create(path).wrap_err_with(|| format!("unable to create {}", path.display()))?;
+        let mut tiff = Self {
+            file,
+            path: path.to_path_buf(),
+            width,
+            rows: 0,
+        };
+        tiff.write_header()
+            .wrap_err_with(|| format!("unable to write {}", path.display()))?;
+        Ok(tiff)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            let mut bytes = Vec::with_capacity(12);
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes
+        };
+
+        let mut header = Vec::with_capacity(DATA_OFFSET as usize);
+        header.extend_from_slice(b"II");
+        header.extend_from_slice(&42u16.to_le_bytes());
+        header.extend_from_slice(&8u32.to_le_bytes());
+        header.extend_from_slice(&ENTRIES.to_le_bytes());
+        for bytes in [
+            entry(TAG_IMAGE_WIDTH, TYPE_LONG, 1, self.width),
+            entry(TAG_IMAGE_LENGTH, TYPE_LONG, 1, 0),
+            entry(TAG_BITS_PER_SAMPLE, TYPE_SHORT, 3, BITS_OFFSET),
+            // no compression, RGB
+            entry(TAG_COMPRESSION, TYPE_SHORT, 1, 1),
+            entry(TAG_PHOTOMETRIC, TYPE_SHORT, 1, 2),
+            entry(TAG_STRIP_OFFSETS, TYPE_LONG, 1, DATA_OFFSET),
+            entry(TAG_SAMPLES_PER_PIXEL, TYPE_SHORT, 1, 3),
+            // every row in one strip
+            entry(TAG_ROWS_PER_STRIP, TYPE_LONG, 1, u32::MAX),
+            entry(TAG_STRIP_BYTE_COUNTS, TYPE_LONG, 1, 0),
+            entry(TAG_X_RESOLUTION, TYPE_RATIONAL, 1, RESOLUTION_OFFSET),
+            entry(TAG_Y_RESOLUTION, TYPE_RATIONAL, 1, RESOLUTION_OFFSET),
+            // interleaved samples, no resolution unit
+            entry(TAG_PLANAR_CONFIG, TYPE_SHORT, 1, 1),
+            entry(TAG_RESOLUTION_UNIT, TYPE_SHORT, 1, 1),
+        ] {
+            header.extend_from_slice(&bytes);
+        }
+        header.extend_from_slice(&0u32.to_le_bytes());
+        for _ in 0..3 {
+            header.extend_from_slice(&8u16.to_le_bytes());
+        }
+        header.extend_from_slice(&1u32.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+
+        self.file.write_all(&header)
+    }
+
+    /// Append whole rows of RGB pixels
+    pub fn push(&mut self, pixels: &[u8]) -> Result<()> {
+        let row_len = self.width as usize * 3;
+        debug_assert_eq!(pixels.len() % row_len, 0, "partial row");
+        let rows = self.rows + (pixels.len() / row_len) as u32;
+        let size = rows as u64 * row_len as u64;
+
+        if DATA_OFFSET as u64 + size > u32::MAX as u64 {
+            return Err(eyre!("{} would exceed 4 GiB", self.path.display()));
+        }
+
+        // the rows first, so that the header never covers missing ones
+        let file = &mut self.file;
+        file.seek(SeekFrom::End(0))
+            .and_then(|_| file.write_all(pixels))
+            .and_then(|_| file.seek(SeekFrom::Start(IMAGE_LENGTH_VALUE)))
+            .and_then(|_| file.write_all(&rows.to_le_bytes()))
+            .and_then(|_| file.seek(SeekFrom::Start(STRIP_BYTE_COUNTS_VALUE)))
+            .and_then(|_| file.write_all(&(size as u32).to_le_bytes()))
+            .wrap_err_with(|| format!("unable to write {}", self.path.display()))?;
+
+        self.rows = rows;
+        Ok(())
+    }
+}
+
+/// Sample layout of a PNG
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PngKind {
+    /// 8-bit RGB
+    Rgb,
+    /// 16-bit grayscale, samples big endian
+    Gray16,
+}
+
+/// PNG whose rows are spooled to `<path>.rows` until it is closed
+pub struct PngStream {
+    path: PathBuf,
+    spool_path: PathBuf,
+    spool: BufWriter<File>,
+    kind: PngKind,
+    width: u32,
+    rows: u32,
+}
+
+impl PngStream {
+    /// Start an image of `width` pixels, the file itself is written by `finish`
+    pub fn create(path: &Path, width: u32, kind: PngKind) -> Result<Self> {
+        let spool_path = with_suffix(path, ".rows");
+        let spool = File::create(&spool_path)
+            .wrap_err_with(|| format!("unable to create {}", spool_path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            spool_path,
+            spool: BufWriter::new(spool),
+            kind,
+            width,
+            rows: 0,
+        })
+    }
+
+    fn row_len(&self) -> usize {
+        match self.kind {
+            PngKind::Rgb => self.width as usize * 3,
+            PngKind::Gray16 => self.width as usize * 2,
+        }
+    }
+
+    /// Append whole rows of samples in PNG order
+    pub fn push(&mut self, samples: &[u8]) -> Result<()> {
+        debug_assert_eq!(samples.len() % self.row_len(), 0, "partial row");
+        self.spool
+            .write_all(samples)
+            .wrap_err_with(|| format!("unable to write {}", self.spool_path.display()))?;
+        self.rows += (samples.len() / self.row_len()) as u32;
+        Ok(())
+    }
+
+    /// Compress the spooled rows into the image and remove the spool
+    pub fn finish(self) -> Result<()> {
+        let Self {
+            path,
+            spool_path,
+            spool,
+            kind,
+            width,
+            rows,
+        } = self;
+        let spool = spool
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .wrap_err_with(|| format!("unable to write {}", spool_path.display()))?;
+        drop(spool);
+
+        if rows > 0 {
+            // write next to the target and rename, so readers never see a half written file
+            let partial = with_suffix(&path, ".part");
+            encode_png(&spool_path, &partial, width, rows, kind)
+                .and_then(|_| std::fs::rename(&partial, &path).map_err(Into::into))
+                .wrap_err_with(|| format!("unable to write {}", path.display()))?;
+        }
+        std::fs::remove_file(&spool_path)
+            .wrap_err_with(|| format!("unable to remove {}", spool_path.display()))
+    }
+}
+
+fn encode_png(spool: &Path, path: &Path, width: u32, height: u32, kind: PngKind) -> Result<()> {
+    let mut rows = BufReader::new(File::open(spool)?);
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    match kind {
+        PngKind::Rgb => {
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+        }
+        PngKind::Gray16 => {
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Sixteen);
+        }
+    }
+    let mut writer = encoder.write_header()?.into_stream_writer()?;
+    std::io::copy(&mut rows, &mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageReader, Rgb};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("stream-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn tiff_is_readable_after_every_row() {
+        let path = temp_path("rows.tiff");
+        let mut tiff = TiffStream::create(&path, 2).unwrap();
+
+        tiff.push(&[1, 2, 3, 4, 5, 6]).unwrap();
+        let img = ImageReader::open(&path)
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(img.dimensions(), (2, 1));
+        assert_eq!(img.get_pixel(1, 0), &Rgb([4, 5, 6]));
+
+        tiff.push(&[7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18])
+            .unwrap();
+        let img = ImageReader::open(&path)
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(img.dimensions(), (2, 3));
+        assert_eq!(img.get_pixel(0, 2), &Rgb([13, 14, 15]));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn png_is_written_when_finished() {
+        let path = temp_path("rows.png");
+        let mut png = PngStream::create(&path, 2, PngKind::Gray16).unwrap();
+        png.push(&[0, 1, 0, 2]).unwrap();
+        png.push(&[1, 0, 255, 255]).unwrap();
+        assert!(!path.exists());
+
+        png.finish().unwrap();
+        let img = ImageReader::open(&path)
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_luma16();
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(img.into_raw(), vec![1, 2, 256, 65535]);
+        assert!(!with_suffix(&path, ".rows").exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
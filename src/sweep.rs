@@ -9,10 +9,13 @@
 
 // Date, Time, Hz Low, Hz High, Hz bin width, Num Samples, dB, dB, dB, dB, dB, [...], dB
 
-use log::info;
+use std::io::BufRead;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use clap::ValueEnum;
 
 /// Mode for downscaling db values
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
 pub enum DownscaleMode {
     /// Use the average value in each segment
     Average,
@@ -27,6 +30,7 @@ struct SweepLine {
     hz_low: u64,
     hz_high: u64,
     hz_bin_width: f32,
+    #[allow(dead_code)]
     num_samples: u32,
     db: Vec<f32>,
 }
@@ -35,18 +39,25 @@ struct SweepLine {
 // this is not the complete spectrum, but a single line
 impl SweepLine {
     fn from_line(line: &str) -> Self {
+        Self::parse(line).expect("malformed hackrf_sweep line")
+    }
+
+    fn parse(line: &str) -> Option<Self> {
         let parts: Vec<&str> = line.split(",").map(|x| x.trim()).collect();
+        if parts.len() < 7 {
+            return None;
+        }
         let date = parts[0].to_string();
         let time = parts[1].to_string();
-        let hz_low = parts[2].parse::<u64>().unwrap();
-        let hz_high = parts[3].parse::<u64>().unwrap();
-        let hz_bin_width = parts[4].parse::<f32>().unwrap();
-        let num_samples = parts[5].parse::<u32>().unwrap();
-        let db: Vec<f32> = parts[6..]
+        let hz_low = parts[2].parse::<u64>().ok()?;
+        let hz_high = parts[3].parse::<u64>().ok()?;
+        let hz_bin_width = parts[4].parse::<f32>().ok()?;
+        let num_samples = parts[5].parse::<u32>().ok()?;
+        let db = parts[6..]
             .iter()
-            .map(|x| x.parse::<f32>().unwrap())
-            .collect();
-        Self {
+            .map(|x| x.parse::<f32>().ok())
+            .collect::<Option<Vec<f32>>>()?;
+        Some(Self {
             date,
             time,
             hz_low,
@@ -54,7 +65,20 @@ impl SweepLine {
             hz_bin_width,
             num_samples,
             db,
-        }
+        })
+    }
+
+    // hackrf_sweep prints the local time of the host
+    fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let naive = NaiveDateTime::parse_from_str(
+            &format!("{} {}", self.date, self.time),
+            "%Y-%m-%d %H:%M:%S%.f",
+        )
+        .ok()?;
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
     }
 }
 
@@ -65,10 +89,15 @@ pub struct Sweep {
     pub hz_high: u64,
     pub hz_bin_width: f32,
     pub db: Vec<f32>,
+    /// Time the sweep started
+    pub timestamp: DateTime<Utc>,
 }
 
 impl Sweep {
-    fn from_lines(lines: Vec<SweepLine>) -> Self {
+    fn from_lines(mut lines: Vec<SweepLine>) -> Self {
+        // hackrf_sweep does not print the lines of a sweep in frequency order
+        lines.sort_by_key(|line| line.hz_low);
+
         #[cfg(debug_assertions)]
         for line in &lines {
             assert!(line.hz_bin_width == lines[0].hz_bin_width);
@@ -78,11 +107,13 @@ impl Sweep {
         let hz_high = lines[lines.len() - 1].hz_high;
         let hz_bin_width = lines[0].hz_bin_width;
         let db: Vec<f32> = lines.iter().flat_map(|x| x.db.iter()).cloned().collect();
+        let timestamp = lines[0].timestamp().unwrap_or_else(Utc::now);
         Self {
             hz_low,
             hz_high,
             hz_bin_width,
             db,
+            timestamp,
         }
     }
 
@@ -95,6 +126,124 @@ impl Sweep {
         Self::from_lines(lines)
     }
 
+    /// Read a recording made with hackrf_sweep (without -1), one sweep at a time
+    ///
+    /// A new sweep starts when a line repeats a frequency already seen in the
+    /// current one, malformed lines (e.g. a truncated last line) are skipped.
+    pub fn read_hackrf_sweep_recording<R: BufRead>(reader: R) -> impl Iterator<Item = Sweep> {
+        let mut lines = reader
+            .lines()
+            .map_while(|l| l.ok())
+            .filter_map(|l| SweepLine::parse(&l));
+        let mut pending: Option<SweepLine> = None;
+
+        std::iter::from_fn(move || {
+            let mut current: Vec<SweepLine> = pending.take().into_iter().collect();
+            for line in lines.by_ref() {
+                if current.iter().any(|l| l.hz_low == line.hz_low) {
+                    pending = Some(line);
+                    break;
+                }
+                current.push(line);
+            }
+            if current.is_empty() {
+                None
+            } else {
+                Some(Self::from_lines(current))
+            }
+        })
+    }
+
+    /// Combine sweeps of the same span bin by bin: the peak or the average of each bin
+    pub fn aggregate(sweeps: &[Sweep], mode: DownscaleMode) -> Option<Sweep> {
+        let first = sweeps.first()?;
+        let bins = sweeps.iter().map(|s| s.db.len()).min().unwrap_or(0);
+
+        let db = (0..bins)
+            .map(|i| {
+                let values = sweeps.iter().map(|s| s.db[i]);
+                match mode {
+                    DownscaleMode::Peak => values.fold(f32::NEG_INFINITY, f32::max),
+                    DownscaleMode::Average => values.sum::<f32>() / sweeps.len() as f32,
+                }
+            })
+            .collect();
+
+        Some(Sweep {
+            hz_low: first.hz_low,
+            hz_high: first.hz_high,
+            hz_bin_width: first.hz_bin_width,
+            db,
+            timestamp: first.timestamp,
+        })
+    }
+
+    /// Keep only the bins whose center lies in [hz_low, hz_high]
+    pub fn crop(&self, hz_low: u64, hz_high: u64) -> Sweep {
+        let bins: Vec<usize> = (0..self.db.len())
+            .filter(|&i| (hz_low..=hz_high).contains(&self.bin_hz(i)))
+            .collect();
+        let (Some(&first), Some(&last)) = (bins.first(), bins.last()) else {
+            return Sweep {
+                hz_low,
+                hz_high: hz_low,
+                hz_bin_width: self.hz_bin_width,
+                db: Vec::new(),
+                timestamp: self.timestamp,
+            };
+        };
+
+        let bin_low = |i: usize| self.hz_low + (i as f64 * self.hz_bin_width as f64) as u64;
+        Sweep {
+            hz_low: bin_low(first),
+            hz_high: bin_low(last + 1).min(self.hz_high),
+            hz_bin_width: self.hz_bin_width,
+            db: self.db[first..=last].to_vec(),
+            timestamp: self.timestamp,
+        }
+    }
+
+    /// Center frequency of the given bin in Hz
+    pub fn bin_hz(&self, bin: usize) -> u64 {
+        self.hz_low + ((bin as f64 + 0.5) * self.hz_bin_width as f64) as u64
+    }
+
+    /// Bin containing the given frequency, if it lies within the sweep
+    pub fn bin_at(&self, hz: u64) -> Option<usize> {
+        if hz < self.hz_low || self.hz_bin_width <= 0.0 {
+            return None;
+        }
+        let bin = ((hz - self.hz_low) as f64 / self.hz_bin_width as f64) as usize;
+        (bin < self.db.len()).then_some(bin)
+    }
+
+    /// Bins whose center lies in [hz_low, hz_high], at least the bin at its
+    /// center; None if the sweep does not cover the range
+    pub fn band(&self, hz_low: u64, hz_high: u64) -> Option<&[f32]> {
+        if self.hz_low > hz_low || self.hz_high < hz_high {
+            return None;
+        }
+        let first = (0..self.db.len()).find(|&i| self.bin_hz(i) >= hz_low);
+        let last = (0..self.db.len()).rfind(|&i| self.bin_hz(i) <= hz_high);
+        match (first, last) {
+            (Some(first), Some(last)) if first <= last => Some(&self.db[first..=last]),
+            _ => {
+                let bin = self.bin_at((hz_low + hz_high) / 2)?;
+                Some(&self.db[bin..=bin])
+            }
+        }
+    }
+
+    /// Total power in dB of the bins of [hz_low, hz_high], see `band`
+    pub fn band_power(&self, hz_low: u64, hz_high: u64) -> Option<f32> {
+        let power: f64 = self
+            .band(hz_low, hz_high)?
+            .iter()
+            .map(|&db| 10f64.powf(db as f64 / 10.0))
+            .sum();
+        Some((10.0 * power.log10()) as f32)
+    }
+
     pub fn pretty_print(&self) -> String {
         format!(
             "Sweep: {} Hz - {} Hz, {} Hz bin width, {} samples, max {} dB, min {} dB",
@@ -151,4 +300,149 @@ impl Sweep {
 
         result
     }
+
+    /// Resample the db values to exactly the given width: downscale when
+    /// narrower, repeat bins (nearest neighbour) when wider
+    pub fn resample(&self, target_width: usize, mode: DownscaleMode) -> Vec<f32> {
+        if target_width < self.db.len() {
+            return self.downscale(target_width, mode);
+        }
+        if self.db.is_empty() {
+            return Vec::new();
+        }
+        (0..target_width)
+            .map(|x| self.db[x * self.db.len() / target_width])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10 bins of 1 MHz from 100 MHz, the value of each bin its index
+    fn sweep() -> Sweep {
+        Sweep {
+            hz_low: 100_000_000,
+            hz_high: 110_000_000,
+            hz_bin_width: 1_000_000.0,
+            db: (0..10).map(|i| i as f32).collect(),
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn crop_keeps_the_bins_centred_in_the_window() {
+        let cropped = sweep().crop(102_000_000, 105_000_000);
+        assert_eq!(cropped.db, vec![2.0, 3.0, 4.0]);
+        assert_eq!(
+            (cropped.hz_low, cropped.hz_high),
+            (102_000_000, 105_000_000)
+        );
+    }
+
+    #[test]
+    fn crop_to_a_partly_overlapping_window() {
+        let cropped = sweep().crop(95_000_000, 102_000_000);
+        assert_eq!(cropped.db, vec![0.0, 1.0]);
+        assert_eq!(
+            (cropped.hz_low, cropped.hz_high),
+            (100_000_000, 102_000_000)
+        );
+
+        let cropped = sweep().crop(108_600_000, 120_000_000);
+        assert_eq!(cropped.db, vec![9.0]);
+        assert_eq!(
+            (cropped.hz_low, cropped.hz_high),
+            (109_000_000, 110_000_000)
+        );
+    }
+
+    #[test]
+    fn crop_outside_the_sweep_is_empty() {
+        let cropped = sweep().crop(200_000_000, 300_000_000);
+        assert!(cropped.db.is_empty());
+        assert_eq!(cropped.hz_low, cropped.hz_high);
+    }
+
+    #[test]
+    fn band_covers_the_bins_centred_in_it() {
+        let sweep = sweep();
+        assert_eq!(
+            sweep.band(102_000_000, 105_000_000),
+            Some(&[2.0, 3.0, 4.0][..])
+        );
+        // narrower than a bin, the bin at its centre
+        assert_eq!(sweep.band(102_100_000, 102_200_000), Some(&[2.0][..]));
+    }
+
+    #[test]
+    fn band_not_fully_in_the_sweep_is_none() {
+        let sweep = sweep();
+        assert_eq!(sweep.band(95_000_000, 102_000_000), None);
+        assert_eq!(sweep.band(109_000_000, 111_000_000), None);
+        assert_eq!(sweep.band(200_000_000, 201_000_000), None);
+    }
+
+    #[test]
+    fn band_power_adds_up_the_bins() {
+        let sweep = Sweep {
+            db: vec![-10.0; 10],
+            ..sweep()
+        };
+        // two bins of 0.1, 0.2 in total
+        let power = sweep.band_power(100_000_000, 102_000_000).unwrap();
+        assert!((power - -6.9897).abs() < 0.001, "{}", power);
+        assert_eq!(sweep.band_power(95_000_000, 102_000_000), None);
+    }
+
+    #[test]
+    fn resample_to_fewer_bins() {
+        let sweep = sweep();
+        assert_eq!(
+            sweep.resample(5, DownscaleMode::Peak),
+            vec![1.0, 3.0, 5.0, 7.0, 9.0]
+        );
+        assert_eq!(
+            sweep.resample(5, DownscaleMode::Average),
+            vec![0.5, 2.5, 4.5, 6.5, 8.5]
+        );
+    }
+
+    #[test]
+    fn resample_to_more_bins_repeats_them() {
+        let resampled = sweep().resample(20, DownscaleMode::Peak);
+        assert_eq!(resampled.len(), 20);
+        assert_eq!(&resampled[..6], &[0.0, 0.0, 1.0, 1.0, 2.0, 2.0]);
+        assert_eq!(resampled[19], 9.0);
+
+        assert_eq!(sweep().resample(10, DownscaleMode::Average), sweep().db);
+        let empty = Sweep {
+            db: Vec::new(),
+            ..sweep()
+        };
+        assert!(empty.resample(20, DownscaleMode::Peak).is_empty());
+    }
+
+    #[test]
+    fn recordings_are_split_into_sweeps() {
+        let recording = "\
+2024-05-31, 16:05:22.5, 5000000, 10000000, 2500000.00, 20, -3.0, -4.0
+2024-05-31, 16:05:22.5, 0, 5000000, 2500000.00, 20, -1.0, -2.0
+2024-05-31, 16:05:23.5, 0, 5000000, 2500000.00, 20, -5.0, -6.0
+2024-05-31, 16:05:23.5, 5000000, 10000000, 2500000.00, 20, -7.0, -8.0
+2024-05-31, 16:05:24.5, 0, 5000000, 2500";
+        let sweeps: Vec<Sweep> = Sweep::read_hackrf_sweep_recording(recording.as_bytes()).collect();
+
+        assert_eq!(sweeps.len(), 2);
+        // lines come out of frequency order
+        assert_eq!(sweeps[0].db, vec![-1.0, -2.0, -3.0, -4.0]);
+        assert_eq!(sweeps[1].db, vec![-5.0, -6.0, -7.0, -8.0]);
+        assert_eq!((sweeps[0].hz_low, sweeps[0].hz_high), (0, 10_000_000));
+        assert_eq!(sweeps[0].hz_bin_width, 2_500_000.0);
+        assert_eq!(
+            (sweeps[1].timestamp - sweeps[0].timestamp).num_milliseconds(),
+            1000
+        );
+    }
 }
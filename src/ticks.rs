@@ -0,0 +1,110 @@
+// "Nice" axis tick generation
+
+/// Smallest step of the form 1, 2 or 5 times a power of ten that divides
+/// `span` into at most `max_ticks` intervals
+pub fn nice_step(span: f64, max_ticks: usize) -> f64 {
+    if span <= 0.0 || max_ticks == 0 {
+        return span.abs().max(f64::MIN_POSITIVE);
+    }
+    let raw = span / max_ticks as f64;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&step| step >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Multiples of a nice step falling inside [min, max]
+pub fn nice_ticks(min: f64, max: f64, max_ticks: usize) -> Vec<f64> {
+    if max <= min || max_ticks == 0 {
+        return Vec::new();
+    }
+    let step = nice_step(max - min, max_ticks);
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    (first..=last).map(|i| i as f64 * step).collect()
+}
+
+/// Most decimals ever used for tick labels
+const MAX_DECIMALS: usize = 9;
+
+/// Number of decimals needed to print values that are multiples of `step`,
+/// e.g. 1 for 0.5 and 2 for 0.25
+pub fn decimals_for_step(step: f64) -> usize {
+    let step = step.abs();
+    (0..MAX_DECIMALS)
+        .find(|&decimals| {
+            let scaled = step * 10f64.powi(decimals as i32);
+            (scaled - scaled.round()).abs() < 1e-6 * scaled.max(1.0)
+        })
+        .unwrap_or(MAX_DECIMALS)
+}
+
+/// Nice interval in seconds for time ticks at least `min_seconds` apart
+pub fn nice_time_step(min_seconds: f64) -> i64 {
+    const STEPS: &[i64] = &[
+        1, 2, 5, 10, 15, 30, 60, 120, 300, 600, 900, 1800, 3600, 7200, 10800, 21600, 43200, 86400,
+    ];
+    STEPS
+        .iter()
+        .copied()
+        .find(|&s| s as f64 >= min_seconds)
+        .unwrap_or(86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_one_two_or_five_times_a_power_of_ten() {
+        assert_eq!(nice_step(100.0, 10), 10.0);
+        assert_eq!(nice_step(100.0, 6), 20.0);
+        assert_eq!(nice_step(100.0, 30), 5.0);
+        assert_eq!(nice_step(1.0, 4), 0.5);
+        assert_eq!(nice_step(0.1, 5), 0.02);
+    }
+
+    #[test]
+    fn ticks_are_multiples_of_the_step_inside_the_range() {
+        assert_eq!(nice_ticks(0.0, 1.0, 4), vec![0.0, 0.5, 1.0]);
+        assert_eq!(nice_ticks(-95.0, -42.0, 5), vec![-80.0, -60.0]);
+        assert_eq!(
+            nice_ticks(2401.0, 2483.0, 5),
+            vec![2420.0, 2440.0, 2460.0, 2480.0]
+        );
+        assert!(nice_ticks(5.0, 5.0, 4).is_empty());
+        assert!(nice_ticks(0.0, 1.0, 0).is_empty());
+    }
+
+    #[test]
+    fn decimals_match_the_step() {
+        assert_eq!(decimals_for_step(10.0), 0);
+        assert_eq!(decimals_for_step(1.0), 0);
+        assert_eq!(decimals_for_step(0.5), 1);
+        assert_eq!(decimals_for_step(0.25), 2);
+        assert_eq!(decimals_for_step(0.2), 1);
+        assert_eq!(decimals_for_step(0.05), 2);
+        assert_eq!(decimals_for_step(nice_step(0.1, 5)), 2);
+    }
+
+    #[test]
+    fn labels_of_half_steps_are_distinct() {
+        let step = nice_step(2.0, 4);
+        let decimals = decimals_for_step(step);
+        let labels: Vec<String> = nice_ticks(1.0, 3.0, 4)
+            .iter()
+            .map(|v| format!("{:.*}", decimals, v))
+            .collect();
+        assert_eq!(labels, ["1.0", "1.5", "2.0", "2.5", "3.0"]);
+    }
+
+    #[test]
+    fn time_steps_round_up_to_a_clock_interval() {
+        assert_eq!(nice_time_step(0.5), 1);
+        assert_eq!(nice_time_step(40.0), 60);
+        assert_eq!(nice_time_step(3000.0), 3600);
+        assert_eq!(nice_time_step(1e9), 86400);
+    }
+}
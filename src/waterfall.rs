@@ -0,0 +1,532 @@
+// Incremental waterfall image writer
+//
+// Every sweep is coloured once into a pixel row, framed with its margins when
+// annotating, and appended to the image file and the raw sidecar as it
+// arrives; no rows are kept in memory. TIFF files grow a row at a time, PNG
+// files are compressed from a spool of their rows when closed, see `stream`.
+// Segments are rotated to a new file after a number of rows or an amount of
+// time.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use clap::ValueEnum;
+use color_eyre::eyre::{Result, WrapErr};
+use image::{Rgb, RgbImage};
+use log::info;
+
+use crate::annotate::{draw_text, text_width, Frame, WaterfallAnnotation, CHAR_HEIGHT};
+use crate::bandplan::BandPlan;
+use crate::colormap::ColorMap;
+use crate::raw::{RawWriter, RAW_EXTENSION};
+use crate::stream::{PngKind, PngStream, TiffStream};
+use crate::sweep::Sweep;
+use crate::SweepParams;
+
+/// Height in pixels of the band plan header row
+pub const BAND_HEADER_HEIGHT: u32 = 12;
+
+/// Maximum height in pixels of the colour bar of annotated images
+pub const COLOR_BAR_HEIGHT: u32 = 256;
+
+/// Image format of the waterfall files
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colour TIFF
+    Tiff,
+    /// Colour PNG
+    Png,
+    /// 16-bit grayscale PNG, dB range mapped linearly to 0-65535 for later re-colouring
+    Gray16,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Png | OutputFormat::Gray16 => "png",
+        }
+    }
+}
+
+/// When to close the current file and start a new one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotationPolicy {
+    /// Maximum number of sweep rows per file
+    pub max_rows: u32,
+    /// Maximum time span of a file
+    pub max_duration: Option<Duration>,
+    /// Rotate on multiples of `max_duration` since the epoch (e.g. on the hour)
+    /// instead of counting from the start of the file
+    pub align_to_clock: bool,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self {
+            max_rows: 3600,
+            max_duration: None,
+            align_to_clock: false,
+        }
+    }
+}
+
+/// Where and how the waterfall files are written
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    pub directory: PathBuf,
+    /// File name without extension; `{date}`, `{time}`, `{band}` and `{index}` are replaced
+    pub template: String,
+    pub format: OutputFormat,
+    pub rotation: RotationPolicy,
+    /// Minimum sweep time between two updates of the index row of the TIFF
+    /// file being written
+    pub refresh: Duration,
+    /// CSV file listing every image, `None` to disable; TIFF files are listed
+    /// as soon as they are created, PNG files once they are closed
+    pub index: Option<PathBuf>,
+    /// Draw frequency and time axes, a colour bar and a title around colour images
+    pub annotate: bool,
+    /// Write a raw data sidecar next to each image, see `raw`
+    pub raw: bool,
+}
+
+impl OutputConfig {
+    /// File name for a segment starting with the given sweep
+    pub fn file_name(&self, sweep: &Sweep, index: u32) -> String {
+        let name = self
+            .template
+            .replace("{date}", &sweep.timestamp.format("%Y%m%d").to_string())
+            .replace("{time}", &sweep.timestamp.format("%H%M%S").to_string())
+            .replace("{band}", &band_label(sweep))
+            .replace("{index}", &format!("{:04}", index));
+        format!("{}.{}", name, self.format.extension())
+    }
+}
+
+// "2400-2500MHz", with decimals only when the edges are not whole MHz
+fn band_label(sweep: &Sweep) -> String {
+    let mhz = |hz: u64| {
+        if hz.is_multiple_of(1_000_000) {
+            format!("{}", hz / 1_000_000)
+        } else {
+            format!("{:.3}", hz as f64 / 1_000_000.0)
+        }
+    };
+    format!("{}-{}MHz", mhz(sweep.hz_low), mhz(sweep.hz_high))
+}
+
+// Image file of a segment, in the output format
+enum ImageFile {
+    Tiff(TiffStream),
+    Png(PngStream),
+}
+
+impl ImageFile {
+    fn push(&mut self, pixels: &[u8]) -> Result<()> {
+        match self {
+            ImageFile::Tiff(tiff) => tiff.push(pixels),
+            ImageFile::Png(png) => png.push(pixels),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ImageFile::Tiff(_) => Ok(()),
+            ImageFile::Png(png) => png.finish(),
+        }
+    }
+
+    /// Whether the file holds the rows pushed so far, rather than when closed
+    fn live(&self) -> bool {
+        matches!(self, ImageFile::Tiff(_))
+    }
+}
+
+// The file being written
+struct Segment {
+    path: PathBuf,
+    width: u32,
+    image: ImageFile,
+    /// Margins of each row, when annotating
+    frame: Option<Frame>,
+    /// Time of the first and the last row
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    rows: u32,
+    /// Time of the last row when the index was last updated
+    indexed: Option<DateTime<Utc>>,
+    hz_low: u64,
+    hz_high: u64,
+    hz_bin_width: f32,
+    /// Colour range of the whole file
+    min_db: f32,
+    max_db: f32,
+    raw: Option<RawWriter>,
+}
+
+impl Segment {
+    // Line of the index describing the file
+    fn index_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{}\n",
+            self.path.display(),
+            self.start.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.end.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.rows,
+            self.hz_low,
+            self.hz_high,
+            self.hz_bin_width,
+            self.min_db,
+            self.max_db,
+        )
+    }
+}
+
+pub struct WaterfallWriter {
+    config: OutputConfig,
+    band_plans: Vec<BandPlan>,
+    title: String,
+    colormap: ColorMap,
+    min_db: f32,
+    max_db: f32,
+    file_index: u32,
+    segment: Option<Segment>,
+}
+
+impl WaterfallWriter {
+    pub fn new(
+        config: OutputConfig,
+        band_plans: Vec<BandPlan>,
+        colormap: ColorMap,
+        params: &SweepParams,
+    ) -> Self {
+        Self {
+            config,
+            band_plans,
+            title: params.summary(),
+            colormap,
+            min_db: params.min_db,
+            max_db: params.max_db,
+            file_index: 0,
+            segment: None,
+        }
+    }
+
+    /// Replace the default title, the radio settings
+    pub fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
+    /// Change the colour range, taking effect with the next file so that
+    /// all the rows of a file share the same scale
+    pub fn set_db_range(&mut self, min_db: f32, max_db: f32) {
+        self.min_db = min_db;
+        self.max_db = max_db;
+    }
+
+    /// Append a sweep as a new row of the current file
+    pub fn push(&mut self, sweep: &Sweep) -> Result<()> {
+        let width = sweep.db.len() as u32;
+        if width == 0 {
+            return Ok(());
+        }
+
+        if self.needs_rotation(sweep) {
+            self.rotate(sweep)?;
+        }
+
+        let segment = self.segment.as_mut().expect("segment opened by rotate");
+        let (min_db, max_db) = (segment.min_db, segment.max_db);
+        if self.config.format == OutputFormat::Gray16 {
+            let row: Vec<u8> = gray_row(sweep, min_db, max_db)
+                .flat_map(u16::to_be_bytes)
+                .collect();
+            segment.image.push(&row)?;
+        } else {
+            let row: Vec<u8> = sweep
+                .db
+                .iter()
+                .flat_map(|&db| {
+                    let (r, g, b) = self.colormap.eval_db(db, min_db, max_db);
+                    [r, g, b]
+                })
+                .collect();
+            match &mut segment.frame {
+                Some(frame) => segment.image.push(&frame.row(&row, sweep.timestamp))?,
+                None => segment.image.push(&row)?,
+            }
+        }
+        segment.end = sweep.timestamp;
+        segment.rows += 1;
+        if let Some(raw) = &mut segment.raw {
+            raw.push(sweep)?;
+        }
+
+        // the first row is listed right away so the file shows up
+        let refresh = TimeDelta::from_std(self.config.refresh).unwrap_or(TimeDelta::MAX);
+        if segment.image.live()
+            && segment
+                .indexed
+                .is_none_or(|time| sweep.timestamp - time >= refresh)
+        {
+            segment.indexed = Some(sweep.timestamp);
+            let (path, row) = (segment.path.clone(), segment.index_row());
+            self.update_index(&path, &row)?;
+        }
+        Ok(())
+    }
+
+    /// Path of the file currently being written
+    pub fn current_path(&self) -> Option<&Path> {
+        self.segment.as_ref().map(|s| s.path.as_path())
+    }
+
+    /// Close the current file and list it in the index
+    pub fn finish(&mut self) -> Result<()> {
+        let Some(segment) = self.segment.take() else {
+            return Ok(());
+        };
+        // the image first, so that the index never lists a PNG not written yet
+        let row = segment.index_row();
+        segment.image.finish()?;
+        self.update_index(&segment.path, &row)
+    }
+
+    fn needs_rotation(&self, sweep: &Sweep) -> bool {
+        let Some(segment) = &self.segment else {
+            return true;
+        };
+        let rotation = &self.config.rotation;
+
+        let expired = rotation.max_duration.is_some_and(|d| {
+            let d = d.as_secs().max(1) as i64;
+            if rotation.align_to_clock {
+                segment.start.timestamp().div_euclid(d) != sweep.timestamp.timestamp().div_euclid(d)
+            } else {
+                (sweep.timestamp - segment.start).num_seconds() >= d
+            }
+        });
+
+        segment.width != sweep.db.len() as u32
+            || segment.hz_low != sweep.hz_low
+            || segment.hz_high != sweep.hz_high
+            || segment.rows >= rotation.max_rows
+            || expired
+    }
+
+    fn rotate(&mut self, sweep: &Sweep) -> Result<()> {
+        self.finish()?;
+
+        std::fs::create_dir_all(&self.config.directory)
+            .wrap_err_with(|| format!("unable to create {}", self.config.directory.display()))?;
+        let name = self
+            .config
+            .directory
+            .join(self.config.file_name(sweep, self.file_index));
+        self.file_index += 1;
+
+        // never overwrite an earlier image, e.g. two rotations within the same
+        // second or a file of an earlier run: number the name until it is free
+        let mut path = name.clone();
+        for n in 1.. {
+            if !path.exists() {
+                break;
+            }
+            path = name.with_extension(format!("{}.{}", n, self.config.format.extension()));
+        }
+
+        info!("Writing waterfall to {}", path.display());
+
+        let raw = if self.config.raw {
+            Some(RawWriter::create(
+                path.with_extension(RAW_EXTENSION),
+                sweep,
+            )?)
+        } else {
+            None
+        };
+
+        let width = sweep.db.len() as u32;
+        let (min_db, max_db) = (self.min_db, self.max_db);
+
+        // The band header only makes sense in colour, grayscale files hold data only
+        let (image, frame) = match self.config.format {
+            OutputFormat::Gray16 => (
+                ImageFile::Png(PngStream::create(&path, width, PngKind::Gray16)?),
+                None,
+            ),
+            format => {
+                let header = band_header(sweep, width, &self.band_plans);
+                let (frame, top) = if self.config.annotate {
+                    let annotation = WaterfallAnnotation {
+                        title: &format!(
+                            "{}  {}",
+                            sweep.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                            self.title
+                        ),
+                        hz_low: sweep.hz_low,
+                        hz_high: sweep.hz_high,
+                        min_db,
+                        max_db,
+                    };
+                    let bar_height =
+                        (header.height() + self.config.rotation.max_rows).min(COLOR_BAR_HEIGHT);
+                    let (frame, top) = Frame::new(&header, &annotation, bar_height, |db| {
+                        let (r, g, b) = self.colormap.eval_db(db, min_db, max_db);
+                        Rgb([r, g, b])
+                    });
+                    (Some(frame), top)
+                } else {
+                    (None, header)
+                };
+
+                let mut image = if format == OutputFormat::Tiff {
+                    ImageFile::Tiff(TiffStream::create(&path, top.width())?)
+                } else {
+                    ImageFile::Png(PngStream::create(&path, top.width(), PngKind::Rgb)?)
+                };
+                image.push(top.as_raw())?;
+                (image, frame)
+            }
+        };
+
+        self.segment = Some(Segment {
+            path,
+            width,
+            image,
+            frame,
+            start: sweep.timestamp,
+            end: sweep.timestamp,
+            rows: 0,
+            indexed: None,
+            hz_low: sweep.hz_low,
+            hz_high: sweep.hz_high,
+            hz_bin_width: sweep.hz_bin_width,
+            min_db,
+            max_db,
+            raw,
+        });
+
+        Ok(())
+    }
+
+    // Add the row of the segment to the index, or replace it with the new end
+    // time and row count if it is already there
+    fn update_index(&self, path: &Path, row: &str) -> Result<()> {
+        let Some(index) = &self.config.index else {
+            return Ok(());
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(index)
+            .wrap_err_with(|| format!("unable to open index {}", index.display()))?;
+        // loggers sharing an index take turns rewriting it
+        file.lock()
+            .wrap_err_with(|| format!("unable to lock index {}", index.display()))?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .wrap_err_with(|| format!("unable to read index {}", index.display()))?;
+        if contents.is_empty() {
+            contents.push_str("file,start,end,rows,hz_low,hz_high,hz_bin_width,min_db,max_db\n");
+        }
+
+        let key = format!("{},", path.display());
+        let mut found = false;
+        let mut updated: String = contents
+            .lines()
+            .map(|line| {
+                if line.starts_with(&key) {
+                    found = true;
+                    row.to_string()
+                } else {
+                    format!("{}\n", line)
+                }
+            })
+            .collect();
+        if !found {
+            updated.push_str(row);
+        }
+
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(updated.as_bytes()))
+            .wrap_err_with(|| format!("unable to write index {}", index.display()))
+    }
+}
+
+impl Drop for WaterfallWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("{:?}", e);
+        }
+    }
+}
+
+fn gray_row(sweep: &Sweep, min_db: f32, max_db: f32) -> impl Iterator<Item = u16> + '_ {
+    sweep.db.iter().map(move |&db| {
+        let db = ((db - min_db) / (max_db - min_db)).clamp(0.0, 1.0);
+        (db * u16::MAX as f32).round() as u16
+    })
+}
+
+// Each band is painted in its own colour across the columns it covers,
+// with a darker edge so adjacent bands of the same colour stay distinct,
+// and labelled with its name when it is wide enough
+fn band_header(sweep: &Sweep, width: u32, band_plans: &[BandPlan]) -> RgbImage {
+    if band_plans.is_empty() {
+        return RgbImage::new(width, 0);
+    }
+
+    let mut header = RgbImage::new(width, BAND_HEADER_HEIGHT);
+    let span = (sweep.hz_high - sweep.hz_low) as f64;
+    let to_x = |hz: u64| {
+        let hz = hz.clamp(sweep.hz_low, sweep.hz_high);
+        (((hz - sweep.hz_low) as f64 / span) * width as f64) as u32
+    };
+
+    for band in band_plans
+        .iter()
+        .flat_map(|plan| plan.bands_in(sweep.hz_low, sweep.hz_high))
+    {
+        let x0 = to_x(band.start_hz);
+        let x1 = to_x(band.stop_hz).min(width);
+        let (r, g, b) = band.color;
+
+        for x in x0..x1 {
+            let edge = x == x0 || x + 1 == x1;
+            let pixel = if edge {
+                Rgb([r / 2, g / 2, b / 2])
+            } else {
+                Rgb([r, g, b])
+            };
+            for y in 0..BAND_HEADER_HEIGHT {
+                header.put_pixel(x, y, pixel);
+            }
+        }
+
+        let label_width = text_width(&band.name);
+        if label_width + 4 <= x1 - x0 {
+            let x = x0 + (x1 - x0 - label_width) / 2;
+            let y = (BAND_HEADER_HEIGHT - CHAR_HEIGHT) / 2;
+            draw_text(
+                &mut header,
+                x as i32,
+                y as i32,
+                &band.name,
+                Rgb([255, 255, 255]),
+            );
+        }
+    }
+
+    header
+}